@@ -0,0 +1,487 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::time::SystemTime;
+use ipnet::{IpNet, Ipv4Subnets, Ipv6Subnets};
+use ipnetwork::IpNetwork;
+use maxminddb::Reader;
+use serde::Deserialize;
+use anyhow::Result;
+
+use crate::config::{CidrSources, Config, Family, IspMatch};
+use crate::connected::parse_prefix;
+use crate::fsutil::write_atomic;
+
+// delegated-stats 中的一条分配记录
+struct DelegatedRecord {
+    country: String,
+    resource: Resource,
+    // extended 格式中标识同一持有机构的 opaque-id
+    opaque_id: Option<String>,
+}
+
+enum Resource {
+    // ASN 范围(首个 ASN, 数量)
+    Asn(u32, u32),
+    Prefixes(Vec<IpNet>),
+}
+
+// whois 数据库中的 inetnum/inet6num 对象
+struct WhoisRecord {
+    netname: String,
+    country: Option<String>,
+    prefixes: Vec<IpNet>,
+}
+
+// GeoIP 数据库记录中用到的字段，国家库和 ASN 库共用
+#[derive(Deserialize)]
+struct GeoRecord<'a> {
+    #[serde(borrow)]
+    country: Option<GeoCountry<'a>>,
+    autonomous_system_number: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct GeoCountry<'a> {
+    iso_code: Option<&'a str>,
+}
+
+// 已解析的全部来源数据，多个集合共用，避免重复读取大文件
+#[derive(Default)]
+pub struct SourceData {
+    delegated: Vec<DelegatedRecord>,
+    whois: Vec<WhoisRecord>,
+    asn_prefixes: Vec<(IpNet, u32)>,
+    geoip: Vec<Reader<Vec<u8>>>,
+}
+
+impl SourceData {
+    pub fn load(sources: &CidrSources) -> Result<Self> {
+        let mut data = SourceData::default();
+        for path in &sources.delegated {
+            let content = read_source(path)?;
+            data.delegated.extend(content.lines().filter_map(parse_delegated));
+        }
+        for path in &sources.whois {
+            data.whois.extend(parse_whois(&read_source(path)?));
+        }
+        for path in &sources.asn_dumps {
+            let content = read_source(path)?;
+            data.asn_prefixes.extend(parse_asn_dump(&content, file_asn(path)));
+        }
+        for path in &sources.geoip {
+            let reader = Reader::open_readfile(path)
+                .map_err(|e| anyhow::anyhow!("Failed to open GeoIP database {}: {}", path, e))?;
+            data.geoip.push(reader);
+        }
+        Ok(data)
+    }
+    
+    // 按运营商条件筛选地址段：delegated 按国家和 ASN(经 opaque-id 关联)匹配，
+    // whois 按 netname 和国家匹配，ASN 导出文件按 ASN 匹配，GeoIP 按国家和 ASN 匹配，结果合并后聚合
+    pub fn prefixes(&self, isp: &IspMatch, family: Family) -> Vec<IpNet> {
+        let country_matches = |country: &str| {
+            isp.countries.is_empty() || isp.countries.iter().any(|c| c.eq_ignore_ascii_case(country))
+        };
+        let mut prefixes = Vec::new();
+        
+        if !isp.countries.is_empty() || !isp.asn.is_empty() {
+            let opaque_ids: HashSet<&str> = self.delegated.iter()
+                .filter(|record| match record.resource {
+                    Resource::Asn(first, count) => isp.asn.iter()
+                        .any(|asn| *asn >= first && (*asn - first) < count),
+                    Resource::Prefixes(_) => false,
+                })
+                .filter_map(|record| record.opaque_id.as_deref())
+                .collect();
+            for record in &self.delegated {
+                let Resource::Prefixes(records) = &record.resource else {
+                    continue;
+                };
+                let holder_matches = isp.asn.is_empty()
+                    || record.opaque_id.as_deref().is_some_and(|id| opaque_ids.contains(id));
+                if country_matches(&record.country) && holder_matches {
+                    prefixes.extend(records.iter().copied());
+                }
+            }
+        }
+        
+        if !isp.netnames.is_empty() {
+            for record in &self.whois {
+                let netname_matches = isp.netnames.iter().any(|prefix| {
+                    record.netname.to_ascii_uppercase().starts_with(&prefix.to_ascii_uppercase())
+                });
+                if netname_matches && record.country.as_deref().is_none_or(country_matches) {
+                    prefixes.extend(record.prefixes.iter().copied());
+                }
+            }
+        }
+        
+        prefixes.extend(self.asn_prefixes.iter()
+            .filter(|(_, asn)| isp.asn.contains(asn))
+            .map(|(prefix, _)| *prefix));
+        
+        if !isp.countries.is_empty() || !isp.asn.is_empty() {
+            for reader in &self.geoip {
+                prefixes.extend(geoip_prefixes(reader, isp, family));
+            }
+        }
+        
+        let prefixes: Vec<IpNet> = prefixes.into_iter()
+            .filter(|prefix| match family {
+                Family::V4 => matches!(prefix, IpNet::V4(_)),
+                Family::V6 => matches!(prefix, IpNet::V6(_)),
+            })
+            .collect();
+        IpNet::aggregate(&prefixes)
+    }
+}
+
+// 遍历 GeoIP 数据库中该地址族的全部网段，取出国家和 ASN 都匹配的网段
+fn geoip_prefixes(reader: &Reader<Vec<u8>>, isp: &IspMatch, family: Family) -> Vec<IpNet> {
+    let root: IpAddr = match family {
+        Family::V4 => Ipv4Addr::UNSPECIFIED.into(),
+        // 只有 IPv4 数据的数据库中没有 IPv6 网段
+        Family::V6 if reader.metadata.ip_version == 6 => Ipv6Addr::UNSPECIFIED.into(),
+        Family::V6 => return Vec::new(),
+    };
+    let Ok(within) = IpNetwork::new(root, 0).map(|root| reader.within::<GeoRecord>(root)) else {
+        return Vec::new();
+    };
+    let Ok(within) = within else {
+        return Vec::new();
+    };
+    // IPv6 数据库把 IPv4 地址映射到 ::/96，并为 ::ffff:0:0/96 和 2002::/16 建立了别名
+    let aliases: Vec<IpNet> = ["::/96", "::ffff:0:0/96", "2002::/16"].iter()
+        .filter_map(|alias| alias.parse().ok())
+        .collect();
+    
+    within.filter_map(|item| item.ok())
+        .filter(|item| {
+            let country = item.info.country.as_ref().and_then(|c| c.iso_code);
+            geoip_matches(isp, country, item.info.autonomous_system_number)
+        })
+        .filter_map(|item| IpNet::new(item.ip_net.ip(), item.ip_net.prefix()).ok())
+        .filter(|prefix| match (family, prefix) {
+            (Family::V4, IpNet::V4(_)) => true,
+            (Family::V6, IpNet::V6(_)) => !aliases.iter().any(|alias| alias.contains(prefix)),
+            _ => false,
+        })
+        .collect()
+}
+
+// 与 delegated 一致：未设置的条件不限制，同时设置国家和 ASN 时两者都须匹配
+fn geoip_matches(isp: &IspMatch, country: Option<&str>, asn: Option<u32>) -> bool {
+    let country_matches = isp.countries.is_empty()
+        || country.is_some_and(|country| isp.countries.iter().any(|c| c.eq_ignore_ascii_case(country)));
+    let asn_matches = isp.asn.is_empty() || asn.is_some_and(|asn| isp.asn.contains(&asn));
+    country_matches && asn_matches
+}
+
+fn read_source(path: &str) -> Result<String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read CIDR source {}: {}", path, e))
+}
+
+// 解析 registry|cc|type|start|value|date|status[|opaque-id] 格式的记录，
+// 跳过版本行、汇总行和未分配的记录
+fn parse_delegated(line: &str) -> Option<DelegatedRecord> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let fields: Vec<&str> = line.trim().split('|').collect();
+    if fields.len() < 7 || !matches!(fields[6], "allocated" | "assigned") {
+        return None;
+    }
+    let resource = match fields[2] {
+        "asn" => Resource::Asn(fields[3].parse().ok()?, fields[4].parse().ok()?),
+        "ipv4" => {
+            // ipv4 记录的 value 是地址数量，不一定是 2 的幂
+            let start: Ipv4Addr = fields[3].parse().ok()?;
+            let count: u32 = fields[4].parse().ok()?;
+            let end = Ipv4Addr::from(u32::from(start).checked_add(count.checked_sub(1)?)?);
+            Resource::Prefixes(Ipv4Subnets::new(start, end, 0).map(IpNet::V4).collect())
+        }
+        "ipv6" => Resource::Prefixes(vec![format!("{}/{}", fields[3], fields[4]).parse().ok()?]),
+        _ => return None,
+    };
+    Some(DelegatedRecord {
+        country: fields[1].to_string(),
+        resource,
+        opaque_id: fields.get(7).map(|id| id.to_string()),
+    })
+}
+
+// 解析 RPSL 格式的 whois 导出，对象之间以空行分隔
+fn parse_whois(content: &str) -> Vec<WhoisRecord> {
+    let mut records = Vec::new();
+    let mut netname = None;
+    let mut country = None;
+    let mut prefixes = Vec::new();
+    // 末尾补一个空行，使最后一个对象也能结束
+    for line in content.lines().chain([""]) {
+        if line.trim().is_empty() {
+            if let Some(netname) = netname.take().filter(|_| !prefixes.is_empty()) {
+                records.push(WhoisRecord {
+                    netname,
+                    country: country.take(),
+                    prefixes: std::mem::take(&mut prefixes),
+                });
+            }
+            country = None;
+            prefixes.clear();
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key {
+            "inetnum" => prefixes.extend(parse_inetnum(value)),
+            "inet6num" => prefixes.extend(value.parse::<IpNet>().ok()),
+            "netname" => netname = Some(value.to_string()),
+            "country" => country = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    records
+}
+
+// inetnum 的值为 "起始地址 - 结束地址"
+fn parse_inetnum(value: &str) -> Vec<IpNet> {
+    let Some((start, end)) = value.split_once('-') else {
+        return Vec::new();
+    };
+    match (start.trim().parse::<Ipv4Addr>(), end.trim().parse::<Ipv4Addr>()) {
+        (Ok(start), Ok(end)) if start <= end => Ipv4Subnets::new(start, end, 0).map(IpNet::V4).collect(),
+        _ => match (start.trim().parse::<Ipv6Addr>(), end.trim().parse::<Ipv6Addr>()) {
+            (Ok(start), Ok(end)) if start <= end => Ipv6Subnets::new(start, end, 0).map(IpNet::V6).collect(),
+            _ => Vec::new(),
+        },
+    }
+}
+
+// 每行 "网段 ASN"，ASN 可带 AS 前缀；只有网段的行属于文件名中的 ASN
+fn parse_asn_dump(content: &str, default_asn: Option<u32>) -> Vec<(IpNet, u32)> {
+    content.lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let prefix = parse_prefix(fields.next()?)?.trunc();
+            let asn = match fields.next() {
+                Some(asn) => parse_asn(asn)?,
+                None => default_asn?,
+            };
+            Some((prefix, asn))
+        })
+        .collect()
+}
+
+fn parse_asn(value: &str) -> Option<u32> {
+    value.trim_start_matches("AS").trim_start_matches("as").parse().ok()
+}
+
+// 文件名形如 AS9808.txt 时返回其中的 ASN
+fn file_asn(path: &str) -> Option<u32> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    stem.strip_prefix("AS").or_else(|| stem.strip_prefix("as"))?.parse().ok()
+}
+
+// 来源文件中最新的修改时间，用于判断是否需要重新生成
+pub fn sources_modified(sources: &CidrSources) -> Option<SystemTime> {
+    sources.delegated.iter()
+        .chain(&sources.whois)
+        .chain(&sources.asn_dumps)
+        .chain(&sources.geoip)
+        .filter_map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+// 生成的网段列表文件中最早的修改时间，有文件不存在时返回 None
+pub fn outputs_modified(config: &Config) -> Option<SystemTime> {
+    config.interfaces.iter()
+        .flat_map(|i| &i.nftables_sets)
+        .filter(|set| set.generate.is_some())
+        .filter_map(|set| set.file.as_ref())
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .try_fold(None, |oldest: Option<SystemTime>, modified| {
+            let modified = modified?;
+            Some(Some(oldest.map_or(modified, |oldest| oldest.min(modified))))
+        })
+        .flatten()
+}
+
+// 为配置了 generate 的集合生成网段列表文件，只生成 only 指定的集合(如果有)，
+// 返回 (集合名, 网段数, 文件是否变化)
+pub fn generate_all(config: &Config, only: Option<&str>) -> Result<Vec<(String, usize, bool)>> {
+    let sets: Vec<_> = config.interfaces.iter()
+        .flat_map(|i| &i.nftables_sets)
+        .filter(|set| set.generate.is_some() && only.is_none_or(|name| name == set.name))
+        .collect();
+    if let Some(name) = only.filter(|_| sets.is_empty()) {
+        return Err(anyhow::anyhow!("Set {} has no generate conditions", name));
+    }
+    if sets.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    let data = SourceData::load(&config.global.cidr_sources)?;
+    let mut results = Vec::new();
+    for set in sets {
+        let (Some(isp), Some(family), Some(file)) = (&set.generate, set.family, &set.file) else {
+            continue;
+        };
+        let prefixes = data.prefixes(isp, family);
+        let changed = write_list(file, &set.name, &prefixes)?;
+        results.push((set.name.clone(), prefixes.len(), changed));
+    }
+    Ok(results)
+}
+
+// 写入网段列表文件，内容未变化时不改写，避免触发集合重新加载
+fn write_list(path: &str, set_name: &str, prefixes: &[IpNet]) -> Result<bool> {
+    let mut content = format!("# 由 mwan3-nft 为集合 {} 生成，请勿手工修改\n", set_name);
+    for prefix in prefixes {
+        content.push_str(&prefix.to_string());
+        content.push('\n');
+    }
+    if std::fs::read_to_string(path).is_ok_and(|existing| existing == content) {
+        return Ok(false);
+    }
+    
+    write_atomic(Path::new(path), &content)?;
+    Ok(true)
+}
+
+// 处理 gen-sets 子命令
+pub async fn run_command(config: &Config, matches: &clap::ArgMatches) -> Result<()> {
+    let config = config.clone();
+    let only = matches.get_one::<String>("set").cloned();
+    let results = tokio::task::spawn_blocking(move || generate_all(&config, only.as_deref())).await??;
+    if results.is_empty() {
+        println!("没有配置 generate 的集合");
+    }
+    for (name, count, changed) in results {
+        println!("集合 {}: {} 条网段{}", name, count, if changed { "" } else { " (未变化)" });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn nets(values: &[&str]) -> Vec<IpNet> {
+        values.iter().map(|v| v.parse().unwrap()).collect()
+    }
+    
+    fn prefixes(record: &DelegatedRecord) -> &[IpNet] {
+        match &record.resource {
+            Resource::Prefixes(prefixes) => prefixes,
+            Resource::Asn(..) => panic!("expected prefixes"),
+        }
+    }
+    
+    #[test]
+    fn delegated_ipv4_count_need_not_be_power_of_two() {
+        // 768 = 512 + 256
+        let record = parse_delegated("apnic|CN|ipv4|1.0.8.0|768|20110412|allocated").unwrap();
+        assert_eq!(record.country, "CN");
+        assert_eq!(prefixes(&record), nets(&["1.0.8.0/23", "1.0.10.0/24"]).as_slice());
+        assert!(record.opaque_id.is_none());
+        
+        let record = parse_delegated("apnic|CN|ipv4|1.0.1.0|256|20110414|allocated").unwrap();
+        assert_eq!(prefixes(&record), nets(&["1.0.1.0/24"]).as_slice());
+    }
+    
+    #[test]
+    fn delegated_extended_record_carries_opaque_id() {
+        let record = parse_delegated("apnic|CN|asn|4134|2|20020801|allocated|A92E1062").unwrap();
+        assert!(matches!(record.resource, Resource::Asn(4134, 2)));
+        assert_eq!(record.opaque_id.as_deref(), Some("A92E1062"));
+        
+        let record = parse_delegated("apnic|CN|ipv6|240e::|20|20130827|allocated|A92E1062").unwrap();
+        assert_eq!(prefixes(&record), nets(&["240e::/20"]).as_slice());
+        assert_eq!(record.opaque_id.as_deref(), Some("A92E1062"));
+    }
+    
+    #[test]
+    fn delegated_skips_header_summary_and_unallocated_lines() {
+        assert!(parse_delegated("# comment").is_none());
+        assert!(parse_delegated("2|apnic|20240101|71234|19830613|20231231|+1000").is_none());
+        assert!(parse_delegated("apnic|*|ipv4|*|45678|summary").is_none());
+        assert!(parse_delegated("apnic||ipv4|1.1.1.0|256||available").is_none());
+        assert!(parse_delegated("apnic|CN|ipv4|1.0.1.0|0|20110414|allocated").is_none());
+        assert!(parse_delegated("apnic|CN|ipv4|255.255.255.0|512|20110414|allocated").is_none());
+    }
+    
+    #[test]
+    fn inetnum_range_splits_into_prefixes() {
+        assert_eq!(parse_inetnum("1.0.8.0 - 1.0.10.255"), nets(&["1.0.8.0/23", "1.0.10.0/24"]));
+        assert_eq!(parse_inetnum("2001:db8:: - 2001:db8::ffff"), nets(&["2001:db8::/112"]));
+        assert!(parse_inetnum("1.0.10.255 - 1.0.8.0").is_empty());
+        assert!(parse_inetnum("1.0.8.0").is_empty());
+        assert!(parse_inetnum("1.0.8.0 - 2001:db8::").is_empty());
+    }
+    
+    #[test]
+    fn whois_last_object_without_trailing_blank_line() {
+        let content = "inetnum:        1.0.8.0 - 1.0.15.255\n\
+            netname:        CHINANET-GD\n\
+            country:        CN\n\
+            \n\
+            % comment line\n\
+            \n\
+            inet6num:       240e::/20\n\
+            netname:        CHINANET\n\
+            remarks:        no country";
+        let records = parse_whois(content);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].netname, "CHINANET-GD");
+        assert_eq!(records[0].country.as_deref(), Some("CN"));
+        assert_eq!(records[0].prefixes, nets(&["1.0.8.0/21"]));
+        assert_eq!(records[1].netname, "CHINANET");
+        assert_eq!(records[1].country, None);
+        assert_eq!(records[1].prefixes, nets(&["240e::/20"]));
+    }
+    
+    #[test]
+    fn whois_object_without_netname_is_dropped() {
+        let records = parse_whois("inetnum: 1.0.8.0 - 1.0.8.255\ncountry: CN\n\nnetname: ORPHAN\n");
+        assert!(records.is_empty());
+    }
+    
+    #[test]
+    fn asn_dump_uses_file_asn_for_bare_prefixes() {
+        let content = "1.0.8.0/21 AS4134\n240e::/20 4134 # comment\n1.2.3.0/24\n# only comment\ninvalid AS1\n";
+        assert_eq!(parse_asn_dump(content, None), vec![
+            ("1.0.8.0/21".parse().unwrap(), 4134),
+            ("240e::/20".parse().unwrap(), 4134),
+        ]);
+        assert_eq!(parse_asn_dump("1.2.3.4/24\n", file_asn("/tmp/AS9808.txt")), vec![
+            ("1.2.3.0/24".parse().unwrap(), 9808),
+        ]);
+    }
+    
+    #[test]
+    fn geoip_requires_both_country_and_asn_when_both_set() {
+        let isp = IspMatch {
+            countries: vec!["cn".into()],
+            asn: vec![4134],
+            ..Default::default()
+        };
+        assert!(geoip_matches(&isp, Some("CN"), Some(4134)));
+        assert!(!geoip_matches(&isp, Some("CN"), Some(9808)));
+        assert!(!geoip_matches(&isp, Some("US"), Some(4134)));
+        assert!(!geoip_matches(&isp, None, Some(4134)));
+        assert!(!geoip_matches(&isp, Some("CN"), None));
+        
+        let countries_only = IspMatch { countries: vec!["CN".into()], ..Default::default() };
+        assert!(geoip_matches(&countries_only, Some("CN"), None));
+        assert!(!geoip_matches(&countries_only, Some("US"), Some(4134)));
+        
+        let asn_only = IspMatch { asn: vec![4134], ..Default::default() };
+        assert!(geoip_matches(&asn_only, None, Some(4134)));
+        assert!(!geoip_matches(&asn_only, Some("CN"), Some(9808)));
+    }
+}
\ No newline at end of file
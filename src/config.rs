@@ -1,26 +1,174 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
 use anyhow::Result;
+use ipnet::IpNet;
 use tokio::fs;
 
+use crate::schedule::Schedule;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub global: GlobalConfig,
     pub interfaces: Vec<Interface>,
     pub policies: Vec<Policy>,
+    // 按时间段切换策略或排空接口的计划
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<Schedule>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalConfig {
     pub policy: String,
+    // IPv6 流量使用的策略，未设置时与 IPv4 相同
+    #[serde(rename = "policy-v6", default, skip_serializing_if = "Option::is_none")]
+    pub policy_v6: Option<String>,
     #[serde(rename = "udp-race")]
     pub udp_race: bool,
     pub mptcp: bool,
     pub tfo: bool,
     #[serde(rename = "health-check")]
     pub health_check: HealthCheckConfig,
+    // 规则同步检查间隔(秒)
+    #[serde(rename = "reconcile-interval", default = "default_reconcile_interval")]
+    pub reconcile_interval: u64,
+    // 运行状态目录，保存覆盖规则和状态文件
+    #[serde(rename = "state-dir", default = "default_state_dir")]
+    pub state_dir: String,
+    // 接口下线时清除经该接口的连接跟踪条目，使客户端立即通过其他接口重连
+    #[serde(rename = "flush-conntrack", default = "default_flush_conntrack")]
+    pub flush_conntrack: bool,
+    // 额外的直连网段，发往这些地址的流量不经过策略分流
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bypass: Vec<IpNet>,
+    // 生成运营商网段列表的数据来源
+    #[serde(rename = "cidr-sources", default)]
+    pub cidr_sources: CidrSources,
+    // 按域名分流时生成的 DNS 服务器配置片段
+    #[serde(rename = "dns-fragments", default)]
+    pub dns_fragments: DnsFragments,
+    // 内置 DNS 转发器，没有 dnsmasq/smartdns 时用于填充域名集合
+    #[serde(rename = "dns-forwarder", default, skip_serializing_if = "Option::is_none")]
+    pub dns_forwarder: Option<DnsForwarderConfig>,
+    // 系统记录各接口 DNS 服务器的文件(OpenWrt 为 resolv.conf.auto)
+    #[serde(rename = "resolv-conf", default = "default_resolv_conf")]
+    pub resolv_conf: String,
+    // nftables 表名、链优先级和标记位，用于与 fw4 等其他规则共存
+    #[serde(default)]
+    pub nftables: NftablesConfig,
+}
+
+fn default_resolv_conf() -> String {
+    "/tmp/resolv.conf.d/resolv.conf.auto".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsForwarderConfig {
+    // 监听地址
+    #[serde(default = "default_dns_listen")]
+    pub listen: SocketAddr,
+    // 上游 DNS 服务器，按顺序尝试
+    pub upstream: Vec<SocketAddr>,
+    // 等待上游应答的超时时间(毫秒)
+    #[serde(default = "default_dns_timeout")]
+    pub timeout: u64,
 }
 
+fn default_dns_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 5353))
+}
+
+fn default_dns_timeout() -> u64 {
+    2000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftablesConfig {
+    #[serde(default = "default_table")]
+    pub table: String,
+    // 基础链的优先级，如 "mangle"、"mangle - 5"、"-160"
+    #[serde(rename = "prerouting-priority", default = "default_priority")]
+    pub prerouting_priority: String,
+    #[serde(rename = "output-priority", default = "default_priority")]
+    pub output_priority: String,
+    // 使用的标记位，接口标记左移到掩码的最低位，掩码外的位保留给其他程序
+    #[serde(rename = "mark-mask", default = "default_mark_mask")]
+    pub mark_mask: u32,
+}
+
+impl Default for NftablesConfig {
+    fn default() -> Self {
+        Self {
+            table: default_table(),
+            prerouting_priority: default_priority(),
+            output_priority: default_priority(),
+            mark_mask: default_mark_mask(),
+        }
+    }
+}
+
+impl NftablesConfig {
+    // 接口标记在 fwmark 中的实际取值
+    pub fn mark_value(&self, mark: u32) -> u32 {
+        mark.checked_shl(self.mark_mask.trailing_zeros()).unwrap_or_default()
+    }
+}
+
+fn default_table() -> String {
+    "mwan3".to_string()
+}
+
+fn default_priority() -> String {
+    "mangle".to_string()
+}
+
+fn default_mark_mask() -> u32 {
+    u32::MAX
+}
+
+// dnsmasq/smartdns 配置片段的输出路径，解析结果由 DNS 服务器写入对应的集合
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsFragments {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dnsmasq: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smartdns: Option<String>,
+}
+
+// 运营商网段数据来源，用于生成受管集合的网段列表文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CidrSources {
+    // RIR delegated-stats 文件，extended 格式中同一机构的 ASN 和地址段共享 opaque-id
+    pub delegated: Vec<String>,
+    // whois 数据库导出的 inetnum/inet6num 对象，按 netname 匹配
+    pub whois: Vec<String>,
+    // 按 ASN 导出的网段文件，每行 "网段 ASN"；文件名为 AS<号码> 时每行只有网段
+    #[serde(rename = "asn-dumps")]
+    pub asn_dumps: Vec<String>,
+    // MaxMind/DB-IP 格式的 GeoIP 数据库(.mmdb)，国家库按国家匹配，ASN 库按 ASN 匹配
+    pub geoip: Vec<String>,
+    // 运行时来源文件更新后自动重新生成网段列表
+    pub refresh: bool,
+}
+
+fn default_flush_conntrack() -> bool {
+    true
+}
+
+fn default_state_dir() -> String {
+    "/var/lib/mwan3-nft".to_string()
+}
+
+fn default_reconcile_interval() -> u64 {
+    5
+}
+
+// 接口权重上限，放大 WEIGHT_SCALE 倍并在嵌套策略中累加后仍不会溢出
+pub const MAX_WEIGHT: u32 = 1000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Interface {
     pub name: String,
@@ -29,17 +177,310 @@ pub struct Interface {
     pub weight: u32,
     pub mark: u32,
     pub enabled: bool,
+    // 优先经该接口转发的地址集合，接口不可用时回落到策略
     #[serde(rename = "nftables-sets")]
-    pub nftables_sets: Vec<String>,
+    pub nftables_sets: Vec<NftablesSet>,
+    // 接口带宽(Mbit/s)，用于自适应负载均衡计算利用率
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<u32>,
+    // 按计费周期统计的流量配额(可选)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaConfig>,
+    // 接口是否提供 IPv6 连接，启用后单独检测 IPv6 健康状态并参与 IPv6 分流
+    #[serde(default)]
+    pub ipv6: bool,
+    // 只能经该接口访问的 DNS 服务器，与从 resolv-conf 中读取的服务器合并
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dns: Vec<IpAddr>,
+}
+
+// 地址族
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Family {
+    #[default]
+    #[serde(rename = "ipv4")]
+    V4,
+    #[serde(rename = "ipv6")]
+    V6,
+}
+
+impl Family {
+    pub const ALL: [Family; 2] = [Family::V4, Family::V6];
+    
+    // nftables 中的协议关键字，如 ip saddr / ip6 saddr
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Family::V4 => "ip",
+            Family::V6 => "ip6",
+        }
+    }
+    
+    pub fn nfproto(self) -> &'static str {
+        match self {
+            Family::V4 => "ipv4",
+            Family::V6 => "ipv6",
+        }
+    }
+    
+    // 链和集合名称的后缀
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Family::V4 => "v4",
+            Family::V6 => "v6",
+        }
+    }
+}
+
+// 接口绑定的 nftables 集合，匹配的流量在接口可用时直接走该接口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "NftablesSetEntry")]
+pub struct NftablesSet {
+    pub name: String,
+    // 匹配目的地址还是源地址
+    #[serde(rename = "match")]
+    pub direction: SetMatch,
+    // 集合的地址族，未设置时从已存在的集合类型中读取
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<Family>,
+    // 由 mwan3-nft 创建和维护，否则集合由外部程序创建和填充
+    pub managed: bool,
+    // 集合元素的来源文件，每行一个网段，文件变化后自动更新集合
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    // 从 cidr-sources 生成网段列表文件时的匹配条件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generate: Option<IspMatch>,
+    // 按域名填充：解析到的地址由 DNS 服务器写入集合，超时后自动删除
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+    // 域名列表文件，每行一个域名
+    #[serde(rename = "domain-files", skip_serializing_if = "Vec::is_empty")]
+    pub domain_files: Vec<String>,
+    // 集合元素的超时时间(秒)，设置后集合为动态集合
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+// 域名集合未设置超时时间时元素的默认超时(秒)
+pub const DEFAULT_DOMAIN_TIMEOUT: u64 = 3600;
+
+impl NftablesSet {
+    pub fn has_domains(&self) -> bool {
+        !self.domains.is_empty() || !self.domain_files.is_empty()
+    }
+    
+    // 元素超时时间，域名集合默认带超时，避免过期的解析结果一直保留
+    pub fn element_timeout(&self) -> Option<u64> {
+        self.timeout.or_else(|| self.has_domains().then_some(DEFAULT_DOMAIN_TIMEOUT))
+    }
+}
+
+// 运营商的识别方式，满足任一条件的地址段都属于该运营商
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IspMatch {
+    // 国家代码，只设置国家时包含这些国家的全部地址段
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub countries: Vec<String>,
+    // 运营商的 ASN
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub asn: Vec<u32>,
+    // whois 中 netname 的前缀，如 "CMNET"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub netnames: Vec<String>,
+}
+
+// 配置中既可以只写集合名，也可以写完整的集合配置
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NftablesSetEntry {
+    Name(String),
+    Set {
+        name: String,
+        #[serde(rename = "match", default)]
+        direction: SetMatch,
+        #[serde(default)]
+        family: Option<Family>,
+        #[serde(default)]
+        managed: bool,
+        #[serde(default)]
+        file: Option<String>,
+        #[serde(default)]
+        generate: Option<IspMatch>,
+        #[serde(default)]
+        domains: Vec<String>,
+        #[serde(rename = "domain-files", default)]
+        domain_files: Vec<String>,
+        #[serde(default)]
+        timeout: Option<u64>,
+    },
+}
+
+impl From<NftablesSetEntry> for NftablesSet {
+    fn from(entry: NftablesSetEntry) -> Self {
+        match entry {
+            NftablesSetEntry::Name(name) => Self {
+                name,
+                direction: SetMatch::default(),
+                family: None,
+                managed: false,
+                file: None,
+                generate: None,
+                domains: Vec::new(),
+                domain_files: Vec::new(),
+                timeout: None,
+            },
+            NftablesSetEntry::Set {
+                name,
+                direction,
+                family,
+                managed,
+                file,
+                generate,
+                domains,
+                domain_files,
+                timeout,
+            } => Self {
+                name,
+                direction,
+                family,
+                managed,
+                file,
+                generate,
+                domains,
+                domain_files,
+                timeout,
+            },
+        }
+    }
+}
+
+// 集合匹配的地址方向，默认按目的地址匹配(如运营商 IP 段)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SetMatch {
+    #[default]
+    #[serde(rename = "daddr")]
+    Daddr,
+    #[serde(rename = "saddr")]
+    Saddr,
+}
+
+impl SetMatch {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            SetMatch::Daddr => "daddr",
+            SetMatch::Saddr => "saddr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    // 每个计费周期的流量上限(收发合计)，如 "50GB"
+    pub limit: String,
+    // 计费周期开始日，每月 1-28 日
+    #[serde(rename = "cycle-start", default = "default_cycle_start")]
+    pub cycle_start: u32,
+    // 用量达到上限的百分比时告警
+    #[serde(default = "default_quota_warning")]
+    pub warning: u8,
+    // 达到上限后的处理方式
+    #[serde(default)]
+    pub action: QuotaAction,
+}
+
+fn default_cycle_start() -> u32 {
+    1
+}
+
+fn default_quota_warning() -> u8 {
+    80
+}
+
+// 流量耗尽后：从所有策略中排除，或降级为没有其他可用接口时才使用
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuotaAction {
+    #[default]
+    #[serde(rename = "exclude")]
+    Exclude,
+    #[serde(rename = "last-resort")]
+    LastResort,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Policy {
+    // 策略名称，未设置时以策略类型作为名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     #[serde(rename = "type")]
     pub policy_type: String,
+    // 策略成员，可以是接口名，也可以是其他策略的名称
+    #[serde(alias = "members")]
     pub interfaces: Vec<String>,
+    // 自适应权重，仅用于 load-balance 策略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adaptive: Option<AdaptiveConfig>,
+    // 负载均衡的分流方式，仅用于 load-balance 策略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<BalanceHash>,
+    // 会话保持：客户端首次分配的接口在超时前保持不变
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticky: Option<StickyConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StickyConfig {
+    // 会话保持超时时间(秒)，每个新连接都会刷新
+    pub timeout: u64,
+}
+
+// 负载均衡分流方式：随机或按地址哈希，哈希可保证同一客户端固定走同一接口
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BalanceHash {
+    #[default]
+    #[serde(rename = "random")]
+    Random,
+    #[serde(rename = "src")]
+    Src,
+    #[serde(rename = "src-dst")]
+    SrcDst,
+    #[serde(rename = "5-tuple")]
+    FiveTuple,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveConfig {
+    // 延迟超过该值(毫秒)后按比例降低权重
+    #[serde(rename = "latency-target")]
+    pub latency_target: u64,
+    // 每个周期权重系数的最大变化量
+    #[serde(rename = "max-step")]
+    pub max_step: f64,
+    // 权重系数下限，保证降级线路仍保留少量流量
+    #[serde(rename = "min-factor")]
+    pub min_factor: f64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            latency_target: 100,
+            max_step: 0.1,
+            min_factor: 0.1,
+        }
+    }
+}
+
+// 策略成员解析结果
+#[derive(Debug, Clone, Copy)]
+pub enum PolicyMember<'a> {
+    Interface(&'a Interface),
+    Policy(&'a Policy),
+}
+
+pub const POLICY_TYPES: &[&str] = &["url-test", "load-balance", "fallback"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthCheckConfig {
     pub timeout: u64,
@@ -55,9 +496,11 @@ impl Config {
     pub async fn load(path: &str) -> Result<Self> {
         let content = fs::read_to_string(path).await?;
         let config: Config = serde_yaml::from_str(&content)?;
+        config.validate()?;
         Ok(config)
     }
     
+    #[allow(dead_code)]
     pub async fn reload(&mut self, path: &str) -> Result<()> {
         let new_config = Self::load(path).await?;
         *self = new_config;
@@ -65,7 +508,454 @@ impl Config {
     }
     
     pub fn validate(&self) -> Result<()> {
-        // 配置验证逻辑占位
+        if self.global.health_check.interval == 0 || self.global.reconcile_interval == 0 {
+            return Err(anyhow::anyhow!("Health check and reconcile intervals must be positive"));
+        }
+        
+        if let Some(forwarder) = self.global.dns_forwarder.as_ref().filter(|f| f.upstream.is_empty()) {
+            return Err(anyhow::anyhow!("DNS forwarder on {} has no upstream servers", forwarder.listen));
+        }
+        
+        let nftables = &self.global.nftables;
+        if nftables.mark_mask == 0 {
+            return Err(anyhow::anyhow!("Mark mask must not be zero"));
+        }
+        if !is_identifier(&nftables.table) {
+            return Err(anyhow::anyhow!("Invalid nftables table name: {}", nftables.table));
+        }
+        for priority in [&nftables.prerouting_priority, &nftables.output_priority] {
+            if !is_priority(priority) {
+                return Err(anyhow::anyhow!("Invalid nftables chain priority: {}", priority));
+            }
+        }
+        
+        let mut names = HashSet::new();
+        let mut set_names = HashSet::new();
+        let mut marks = HashMap::new();
+        for interface in &self.interfaces {
+            if !names.insert(interface.name.as_str()) {
+                return Err(anyhow::anyhow!("Duplicate interface name: {}", interface.name));
+            }
+            // 接口名是链、集合和计数器名称的一部分
+            if !is_identifier(&interface.name) {
+                return Err(anyhow::anyhow!("Invalid interface name: {}", interface.name));
+            }
+            if interface.weight > MAX_WEIGHT {
+                return Err(anyhow::anyhow!(
+                    "Weight of interface {} must not exceed {}", interface.name, MAX_WEIGHT
+                ));
+            }
+            // 标记为 0 表示未分流，移位后必须完整落在掩码内
+            let mark = nftables.mark_value(interface.mark);
+            if mark == 0 || mark >> nftables.mark_mask.trailing_zeros() != interface.mark || mark & !nftables.mark_mask != 0 {
+                return Err(anyhow::anyhow!(
+                    "Mark {} of interface {} does not fit mark mask 0x{:x}",
+                    interface.mark, interface.name, nftables.mark_mask
+                ));
+            }
+            // 标记是各 map 的键，相同标记的接口无法区分
+            if let Some(other) = marks.insert(mark, interface.name.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "Interfaces {} and {} share mark 0x{:x}", other, interface.name, mark
+                ));
+            }
+            for set in &interface.nftables_sets {
+                // 同一集合只能属于一个接口，否则匹配结果取决于规则顺序
+                if !set_names.insert(set.name.as_str()) {
+                    return Err(anyhow::anyhow!("Set {} is bound to more than one interface", set.name));
+                }
+                if !is_identifier(&set.name) {
+                    return Err(anyhow::anyhow!("Invalid set name: {}", set.name));
+                }
+                if set.managed && set.family.is_none() {
+                    return Err(anyhow::anyhow!("Managed set {} must specify a family", set.name));
+                }
+                if set.file.is_some() && !set.managed {
+                    return Err(anyhow::anyhow!("Only managed sets can be loaded from a file: {}", set.name));
+                }
+                if set.has_domains() && !set.managed {
+                    return Err(anyhow::anyhow!("Only managed sets can be filled by domain: {}", set.name));
+                }
+                // 动态集合的元素带超时，不能同时从文件加载
+                if set.element_timeout().is_some() && set.file.is_some() {
+                    return Err(anyhow::anyhow!("Set {} cannot combine a file with domains or a timeout", set.name));
+                }
+                if set.timeout == Some(0) {
+                    return Err(anyhow::anyhow!("Set timeout must be positive: {}", set.name));
+                }
+                if let Some(generate) = &set.generate {
+                    if set.file.is_none() {
+                        return Err(anyhow::anyhow!("Generated set {} must specify a file", set.name));
+                    }
+                    if generate.countries.is_empty() && generate.asn.is_empty() && generate.netnames.is_empty() {
+                        return Err(anyhow::anyhow!("Generated set {} has no match conditions", set.name));
+                    }
+                }
+            }
+            if let Some(quota) = &interface.quota {
+                if parse_size(&quota.limit)? == 0 {
+                    return Err(anyhow::anyhow!("Quota limit must be positive: {}", interface.name));
+                }
+                if !(1..=28).contains(&quota.cycle_start) {
+                    return Err(anyhow::anyhow!("Quota cycle-start must be between 1 and 28: {}", interface.name));
+                }
+                if quota.warning > 100 {
+                    return Err(anyhow::anyhow!("Quota warning must be a percentage: {}", interface.name));
+                }
+            }
+        }
+        
+        for policy in &self.policies {
+            if !POLICY_TYPES.contains(&policy.policy_type.as_str()) {
+                return Err(anyhow::anyhow!("Unknown policy type: {}", policy.policy_type));
+            }
+            // 策略名与接口名共享同一命名空间，否则成员引用会产生歧义
+            if !names.insert(policy.name()) {
+                return Err(anyhow::anyhow!("Duplicate policy or interface name: {}", policy.name()));
+            }
+            // 策略名是策略计数器名称的一部分
+            if !is_identifier(policy.name()) {
+                return Err(anyhow::anyhow!("Invalid policy name: {}", policy.name()));
+            }
+            if policy.hash.is_some() && policy.policy_type != "load-balance" {
+                return Err(anyhow::anyhow!(
+                    "Hash balancing is only supported by load-balance policies: {}", policy.name()
+                ));
+            }
+            if policy.sticky.as_ref().is_some_and(|s| s.timeout == 0) {
+                return Err(anyhow::anyhow!("Sticky timeout must be positive: {}", policy.name()));
+            }
+            if let Some(adaptive) = &policy.adaptive {
+                if policy.policy_type != "load-balance" {
+                    return Err(anyhow::anyhow!(
+                        "Adaptive weights are only supported by load-balance policies: {}", policy.name()
+                    ));
+                }
+                if !(adaptive.max_step > 0.0 && adaptive.max_step <= 1.0) {
+                    return Err(anyhow::anyhow!("Invalid adaptive max-step: {}", adaptive.max_step));
+                }
+                if !(0.0..=1.0).contains(&adaptive.min_factor) {
+                    return Err(anyhow::anyhow!("Invalid adaptive min-factor: {}", adaptive.min_factor));
+                }
+            }
+        }
+        
+        for policy in &self.policies {
+            for member in &policy.interfaces {
+                if self.find_member(member).is_none() {
+                    return Err(anyhow::anyhow!(
+                        "Policy {} references unknown member: {}", policy.name(), member
+                    ));
+                }
+            }
+        }
+        
+        // 检测策略之间的循环引用
+        let mut visited = HashSet::new();
+        for policy in &self.policies {
+            let mut path = Vec::new();
+            self.check_policy_cycle(policy, &mut path, &mut visited)?;
+        }
+        
+        if self.find_policy(&self.global.policy).is_none() {
+            return Err(anyhow::anyhow!("Policy not found: {}", self.global.policy));
+        }
+        if let Some(policy) = self.global.policy_v6.as_ref().filter(|p| self.find_policy(p).is_none()) {
+            return Err(anyhow::anyhow!("Policy not found: {}", policy));
+        }
+        
+        let mut schedule_names = HashSet::new();
+        for schedule in &self.schedules {
+            if !schedule_names.insert(schedule.name.as_str()) {
+                return Err(anyhow::anyhow!("Duplicate schedule name: {}", schedule.name));
+            }
+            schedule.validate(self)?;
+        }
+        
         Ok(())
     }
+    
+    pub fn check_policy_cycle<'a>(
+        &'a self,
+        policy: &'a Policy,
+        path: &mut Vec<&'a str>,
+        visited: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        if let Some(pos) = path.iter().position(|name| *name == policy.name()) {
+            let mut cycle = path[pos..].to_vec();
+            cycle.push(policy.name());
+            return Err(anyhow::anyhow!("Policy cycle detected: {}", cycle.join(" -> ")));
+        }
+        if visited.contains(policy.name()) {
+            return Ok(());
+        }
+        
+        path.push(policy.name());
+        for member in &policy.interfaces {
+            if let Some(PolicyMember::Policy(nested)) = self.find_member(member) {
+                self.check_policy_cycle(nested, path, visited)?;
+            }
+        }
+        path.pop();
+        visited.insert(policy.name());
+        
+        Ok(())
+    }
+    
+    pub fn find_interface(&self, name: &str) -> Option<&Interface> {
+        self.interfaces.iter().find(|i| i.name == name)
+    }
+    
+    pub fn find_policy(&self, name: &str) -> Option<&Policy> {
+        self.policies.iter().find(|p| p.name() == name)
+    }
+    
+    // 配置中明确指定了地址族的集合
+    pub fn set_families(&self) -> HashMap<String, Family> {
+        self.interfaces.iter()
+            .flat_map(|i| &i.nftables_sets)
+            .filter_map(|set| Some((set.name.clone(), set.family?)))
+            .collect()
+    }
+    
+    pub fn find_member(&self, name: &str) -> Option<PolicyMember<'_>> {
+        self.find_interface(name)
+            .map(PolicyMember::Interface)
+            .or_else(|| self.find_policy(name).map(PolicyMember::Policy))
+    }
+}
+
+impl Policy {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.policy_type)
+    }
+}
+
+// nft 标识符：字母、下划线或点开头，之后为字母、数字和 "/-_."；
+// 内核限制名称长度为 256，留出生成的链、集合名称前后缀的长度
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "/-_.".contains(c))
+        && name.len() <= 200
+}
+
+// 链优先级：整数，或优先级名称加可选的 "+ n"/"- n"，如 "mangle - 5"
+fn is_priority(value: &str) -> bool {
+    const PRIORITY_NAMES: &[&str] = &["raw", "mangle", "dstnat", "filter", "security", "srcnat"];
+    
+    let value = value.trim();
+    if value.parse::<i32>().is_ok() {
+        return true;
+    }
+    let (name, offset) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+    if !PRIORITY_NAMES.contains(&name) {
+        return false;
+    }
+    let offset = offset.trim();
+    if offset.is_empty() {
+        return true;
+    }
+    offset.strip_prefix(['+', '-'])
+        .is_some_and(|n| n.trim().parse::<u32>().is_ok())
+}
+
+// 解析 "300ms"、"10s"、"1h30m" 形式的时长，纯数字按秒处理
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(anyhow::anyhow!("Invalid duration: {}", value));
+        }
+        let number: u64 = rest[..digits].parse()?;
+        rest = &rest[digits..];
+        
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit = &rest[..unit_len];
+        rest = &rest[unit_len..];
+        
+        total += match unit {
+            "ms" => Duration::from_millis(number),
+            "s" => Duration::from_secs(number),
+            "m" => Duration::from_secs(number * 60),
+            "h" => Duration::from_secs(number * 3600),
+            "d" => Duration::from_secs(number * 86400),
+            _ => return Err(anyhow::anyhow!("Invalid duration unit in: {}", value)),
+        };
+    }
+    
+    Ok(total)
+}
+
+// 解析流量大小，如 "500MB"、"50GB"、"1.5T"，按十进制单位计算，不带单位时为字节
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let number: f64 = value[..split].parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size: {}", value))?;
+    
+    let multiplier = match value[split..].trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1u64,
+        "K" | "KB" => 1_000,
+        "M" | "MB" => 1_000_000,
+        "G" | "GB" => 1_000_000_000,
+        "T" | "TB" => 1_000_000_000_000,
+        _ => return Err(anyhow::anyhow!("Invalid size unit in: {}", value)),
+    };
+    
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // 两个接口 wan1/wan2 加上给定的策略列表，默认策略为第一个策略
+    fn config(policies: &str) -> Config {
+        let yaml = format!(r#"
+global:
+  policy: "a"
+  udp-race: false
+  mptcp: false
+  tfo: false
+  health-check:
+    timeout: 3
+    interval: 10
+    url: "http://example.com"
+    fail-threshold: 3
+    succ-threshold: 2
+interfaces:
+  - name: "wan1"
+    interface-name: "eth1"
+    weight: 1
+    mark: 1
+    enabled: true
+    nftables-sets: []
+  - name: "wan2"
+    interface-name: "eth2"
+    weight: 1
+    mark: 2
+    enabled: true
+    nftables-sets: []
+policies:
+{}
+"#, policies);
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+    
+    fn cycle_error(config: &Config) -> Option<String> {
+        let mut visited = HashSet::new();
+        config.policies.iter()
+            .find_map(|policy| config.check_policy_cycle(policy, &mut Vec::new(), &mut visited).err())
+            .map(|e| e.to_string())
+    }
+    
+    #[test]
+    fn nft_identifiers() {
+        for name in ["mwan3", "wan_1", "cn-ip.v4", "_x", ".y", "a/b"] {
+            assert!(is_identifier(name), "{}", name);
+        }
+        for name in ["", "1wan", "-x", "wan 1", "wan;flush ruleset", "\"x\"", "wan@1", "电信"] {
+            assert!(!is_identifier(name), "{}", name);
+        }
+        assert!(!is_identifier(&"a".repeat(201)));
+    }
+    
+    #[test]
+    fn nft_priorities() {
+        for value in ["mangle", "-150", "0", "mangle - 5", "filter + 10", "raw -1", " srcnat "] {
+            assert!(is_priority(value), "{}", value);
+        }
+        for value in ["", "mangel", "mangle-5", "mangle - x", "mangle * 2", "mangle - -5", "1.5", "mangle; flush"] {
+            assert!(!is_priority(value), "{}", value);
+        }
+    }
+    
+    #[test]
+    fn invalid_names_are_rejected() {
+        let mut config = config(r#"
+  - name: "a"
+    type: "fallback"
+    interfaces: ["wan1"]"#);
+        config.global.nftables.table = "mwan3 x".to_string();
+        assert!(config.validate().is_err());
+        config.global.nftables.table = "mwan3".to_string();
+        config.global.nftables.output_priority = "mangle-5".to_string();
+        assert!(config.validate().is_err());
+        config.global.nftables.output_priority = "mangle - 5".to_string();
+        config.validate().unwrap();
+        config.interfaces[0].name = "wan 1".to_string();
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn interfaces_must_not_share_a_mark() {
+        let mut config = config(r#"
+  - name: "a"
+    type: "fallback"
+    interfaces: ["wan1", "wan2"]"#);
+        config.validate().unwrap();
+        config.interfaces[1].mark = 1;
+        assert_eq!(config.validate().unwrap_err().to_string(), "Interfaces wan1 and wan2 share mark 0x1");
+    }
+    
+    #[test]
+    fn weight_is_bounded() {
+        let mut config = config(r#"
+  - name: "a"
+    type: "load-balance"
+    interfaces: ["wan1", "wan2"]"#);
+        config.interfaces[0].weight = MAX_WEIGHT;
+        config.validate().unwrap();
+        config.interfaces[0].weight = u32::MAX;
+        assert_eq!(config.validate().unwrap_err().to_string(), "Weight of interface wan1 must not exceed 1000");
+    }
+    
+    #[test]
+    fn policy_referencing_itself_is_a_cycle() {
+        let config = config(r#"
+  - name: "a"
+    type: "fallback"
+    interfaces: ["wan1", "a"]"#);
+        assert_eq!(cycle_error(&config).as_deref(), Some("Policy cycle detected: a -> a"));
+    }
+    
+    #[test]
+    fn indirect_cycle_reports_the_loop() {
+        let config = config(r#"
+  - name: "a"
+    type: "fallback"
+    interfaces: ["b"]
+  - name: "b"
+    type: "load-balance"
+    interfaces: ["wan1", "c"]
+  - name: "c"
+    type: "fallback"
+    interfaces: ["wan2", "b"]"#);
+        assert_eq!(cycle_error(&config).as_deref(), Some("Policy cycle detected: b -> c -> b"));
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn shared_nested_policy_is_not_a_cycle() {
+        let config = config(r#"
+  - name: "a"
+    type: "fallback"
+    interfaces: ["b", "c"]
+  - name: "b"
+    type: "load-balance"
+    interfaces: ["c", "wan1"]
+  - name: "c"
+    type: "fallback"
+    interfaces: ["wan2"]"#);
+        assert_eq!(cycle_error(&config), None);
+        config.validate().unwrap();
+    }
 }
\ No newline at end of file
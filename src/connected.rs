@@ -0,0 +1,47 @@
+use std::net::IpAddr;
+use ipnet::IpNet;
+use tokio::process::Command;
+use anyhow::Result;
+
+// 从内核路由表(main 和 local)中读取直连网段和本机地址，
+// 只取内核自动生成的路由，避免把 VPN 等手工添加的路由当作直连网段
+pub async fn learn_prefixes() -> Result<Vec<IpNet>> {
+    let mut prefixes = Vec::new();
+    for family in ["-4", "-6"] {
+        let output = Command::new("ip")
+            .args(["-j", family, "route", "show", "table", "all"])
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("ip route failed: {}", stderr));
+        }
+        
+        let routes: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        prefixes.extend(parse_routes(&routes));
+    }
+    
+    Ok(IpNet::aggregate(&prefixes))
+}
+
+fn parse_routes(routes: &serde_json::Value) -> Vec<IpNet> {
+    routes.as_array()
+        .into_iter()
+        .flatten()
+        .filter(|route| route["protocol"] == "kernel" && route.get("gateway").is_none())
+        .filter(|route| matches!(route["table"].as_str(), None | Some("main") | Some("local")))
+        .filter(|route| matches!(
+            route["type"].as_str(),
+            None | Some("unicast") | Some("local") | Some("broadcast") | Some("multicast")
+        ))
+        .filter_map(|route| parse_prefix(route["dst"].as_str()?))
+        .filter(|prefix| prefix.prefix_len() > 0)
+        .collect()
+}
+
+// 路由目标可能是网段，也可能是不带前缀长度的单个地址
+pub fn parse_prefix(dst: &str) -> Option<IpNet> {
+    dst.parse::<IpNet>()
+        .ok()
+        .or_else(|| dst.parse::<IpAddr>().ok().map(IpNet::from))
+}
\ No newline at end of file
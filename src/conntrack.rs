@@ -0,0 +1,48 @@
+use tokio::process::Command;
+use anyhow::Result;
+
+use crate::config::Family;
+
+// 通过 conntrack 工具(ctnetlink)查询和删除连接跟踪条目
+
+pub async fn count_flows(mark: u32, mask: u32) -> Result<usize> {
+    let output = Command::new("conntrack")
+        .args(["-L", "-m", &mark_filter(mark, mask)])
+        .output()
+        .await?;
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("conntrack list failed: {}", stderr));
+    }
+    
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().filter(|line| !line.trim().is_empty()).count())
+}
+
+// 指定地址族时只删除该地址族的条目
+pub async fn flush_mark(mark: u32, mask: u32, family: Option<Family>) -> Result<()> {
+    let mut command = Command::new("conntrack");
+    command.args(["-D", "-m", &mark_filter(mark, mask)]);
+    if let Some(family) = family {
+        command.args(["-f", family.nfproto()]);
+    }
+    let output = command.output().await?;
+    
+    // 没有匹配条目时 conntrack 也会返回失败
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && !stderr.contains("0 flow entries") {
+        return Err(anyhow::anyhow!("conntrack delete failed: {}", stderr));
+    }
+    
+    Ok(())
+}
+
+// 只比较掩码内的标记位: MARK[/MASK]
+fn mark_filter(mark: u32, mask: u32) -> String {
+    if mask == u32::MAX {
+        mark.to_string()
+    } else {
+        format!("{}/{}", mark, mask)
+    }
+}
\ No newline at end of file
@@ -32,8 +32,6 @@ impl DaemonManager {
     #[cfg(unix)]
     fn fork_and_detach(&self) -> Result<()> {
         // Fork进程并脱离终端占位
-        use std::os::unix::process::CommandExt;
-        
         // 第一次fork
         let pid = unsafe { libc::fork() };
         if pid < 0 {
@@ -101,12 +99,9 @@ impl DaemonManager {
     
     pub fn is_running(&self) -> bool {
         // 检查daemon是否正在运行占位
-        if let Ok(pid_str) = std::fs::read_to_string(&self.pid_file) {
-            if let Ok(pid) = pid_str.trim().parse::<u32>() {
-                return self.check_process_exists(pid);
-            }
-        }
-        false
+        self.read_pid()
+            .map(|pid| self.check_process_exists(pid))
+            .unwrap_or(false)
     }
     
     fn check_process_exists(&self, pid: u32) -> bool {
@@ -125,21 +120,26 @@ impl DaemonManager {
     
     pub fn stop_daemon(&self) -> Result<()> {
         // 停止daemon进程占位
-        if let Ok(pid_str) = std::fs::read_to_string(&self.pid_file) {
-            if let Ok(pid) = pid_str.trim().parse::<u32>() {
-                #[cfg(unix)]
-                {
-                    unsafe {
-                        libc::kill(pid as i32, libc::SIGTERM);
-                    }
+        if let Some(pid) = self.read_pid() {
+            #[cfg(unix)]
+            {
+                unsafe {
+                    libc::kill(pid as i32, libc::SIGTERM);
                 }
-                
-                tracing::info!("已发送停止信号给进程: {}", pid);
-                self.remove_pid_file()?;
             }
+            
+            tracing::info!("已发送停止信号给进程: {}", pid);
+            self.remove_pid_file()?;
         }
         Ok(())
     }
+    
+    fn read_pid(&self) -> Option<u32> {
+        std::fs::read_to_string(&self.pid_file).ok()?
+            .trim()
+            .parse::<u32>()
+            .ok()
+    }
 }
 
 // 信号处理占位
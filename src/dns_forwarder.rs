@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::RwLock;
+use anyhow::Result;
+
+use crate::config::{Config, DnsForwarderConfig, Family};
+use crate::domains::{self, DomainTargets};
+use crate::nftables::NftablesManager;
+
+// 写入集合的最短超时，避免 TTL 很短的 CDN 记录在连接建立前就过期
+const MIN_ELEMENT_TIMEOUT: u64 = 60;
+
+// TCP 客户端连接空闲超过该时间后关闭
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+// 未带 EDNS 的查询，UDP 应答不能超过 512 字节
+const DEFAULT_UDP_PAYLOAD: usize = 512;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_OPT: u16 = 41;
+
+// 本地 DNS 转发器：转发查询到上游，并把匹配域名的 A/AAAA 应答写入对应集合
+pub struct DnsForwarder {
+    config: Arc<RwLock<Config>>,
+    nftables: Arc<NftablesManager>,
+}
+
+// 转发单个查询所需的状态，在各查询任务之间共享
+struct ForwarderState {
+    forwarder: DnsForwarderConfig,
+    targets: DomainTargets,
+    // 集合名 -> 元素超时上限
+    timeouts: HashMap<String, u64>,
+    nftables: Arc<NftablesManager>,
+}
+
+impl DnsForwarder {
+    pub fn new(config: Arc<RwLock<Config>>, nftables: Arc<NftablesManager>) -> Self {
+        Self { config, nftables }
+    }
+    
+    pub async fn start(&self) -> Result<()> {
+        let config = self.config.read().await;
+        let Some(forwarder) = config.global.dns_forwarder.clone() else {
+            return Ok(());
+        };
+        let targets = domains::domain_targets(&config).await?;
+        let timeouts = config.interfaces.iter()
+            .flat_map(|i| &i.nftables_sets)
+            .filter_map(|set| Some((set.name.clone(), set.element_timeout()?)))
+            .collect();
+        drop(config);
+        
+        let socket = Arc::new(UdpSocket::bind(forwarder.listen).await?);
+        let listener = TcpListener::bind(forwarder.listen).await?;
+        tracing::info!("DNS 转发器已启动: {}，{} 个分流域名", forwarder.listen, targets.len());
+        let state = Arc::new(ForwarderState {
+            forwarder,
+            targets,
+            timeouts,
+            nftables: self.nftables.clone(),
+        });
+        
+        // UDP 和 TCP 互不影响，任一个出错时另一个继续服务
+        let (udp, tcp) = tokio::join!(serve_udp(socket, state.clone()), serve_tcp(listener, state));
+        udp.and(tcp)
+    }
+}
+
+async fn serve_udp(socket: Arc<UdpSocket>, state: Arc<ForwarderState>) -> Result<()> {
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let (len, client) = socket.recv_from(&mut buf).await?;
+        let query = buf[..len].to_vec();
+        let socket = socket.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = state.handle_udp(&socket, client, &query).await {
+                tracing::debug!("处理来自 {} 的 DNS 查询失败: {}", client, e);
+            }
+        });
+    }
+}
+
+async fn serve_tcp(listener: TcpListener, state: Arc<ForwarderState>) -> Result<()> {
+    loop {
+        let (stream, client) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!("接受 DNS TCP 连接失败: {}", e);
+                continue;
+            }
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = state.handle_tcp(stream).await {
+                tracing::debug!("处理来自 {} 的 DNS TCP 查询失败: {}", client, e);
+            }
+        });
+    }
+}
+
+impl ForwarderState {
+    async fn handle_udp(&self, socket: &UdpSocket, client: SocketAddr, query: &[u8]) -> Result<()> {
+        let response = self.forward(query).await?;
+        // 上游应答被截断时改用 TCP 取得完整应答，写入集合的地址不会缺失；
+        // 完整应答超过客户端的 UDP 负载上限时仍返回截断的应答，客户端会改用 TCP 重新查询
+        let full = if is_truncated(&response) {
+            self.forward_tcp(query).await
+                .inspect_err(|e| tracing::debug!("通过 TCP 重新查询上游失败: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        let reply = match &full {
+            Some(full) if full.len() <= udp_payload_limit(query) => full,
+            _ => &response,
+        };
+        socket.send_to(reply, client).await?;
+        
+        // 先应答客户端再写入集合，不增加解析延迟
+        self.learn(query, full.as_ref().unwrap_or(&response)).await;
+        Ok(())
+    }
+    
+    // 同一连接上可以有多个查询，每个报文前有两字节长度
+    async fn handle_tcp(&self, mut stream: TcpStream) -> Result<()> {
+        loop {
+            let length = match tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_u16()).await {
+                Ok(Ok(length)) => length,
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => return Ok(()),
+            };
+            let mut query = vec![0u8; length as usize];
+            tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut query)).await
+                .map_err(|_| anyhow::anyhow!("Client timed out"))??;
+            
+            let response = self.forward_tcp(&query).await?;
+            write_tcp_message(&mut stream, &response).await?;
+            self.learn(&query, &response).await;
+        }
+    }
+    
+    // 把匹配域名的应答地址写入对应集合
+    async fn learn(&self, query: &[u8], response: &[u8]) {
+        let Some(name) = parse_question(query) else {
+            return;
+        };
+        let Some(sets) = self.match_domain(&name) else {
+            return;
+        };
+        let answers = parse_answers(response);
+        for (family, set) in sets {
+            let elements: Vec<(IpAddr, u64)> = answers.iter()
+                .filter(|(addr, _)| match family {
+                    Family::V4 => addr.is_ipv4(),
+                    Family::V6 => addr.is_ipv6(),
+                })
+                .map(|(addr, ttl)| {
+                    let limit = self.timeouts.get(set).copied().unwrap_or(u64::MAX);
+                    (*addr, u64::from(*ttl).max(MIN_ELEMENT_TIMEOUT).min(limit))
+                })
+                .collect();
+            if elements.is_empty() {
+                continue;
+            }
+            if let Err(e) = self.nftables.add_timed_elements(set, &elements).await {
+                tracing::warn!("写入集合 {} 失败: {}", set, e);
+            }
+        }
+    }
+    
+    // 依次尝试上游服务器，返回第一个应答
+    async fn forward(&self, query: &[u8]) -> Result<Vec<u8>> {
+        let timeout = Duration::from_millis(self.forwarder.timeout);
+        let mut last_error = anyhow::anyhow!("No upstream DNS servers");
+        for upstream in &self.forwarder.upstream {
+            let bind: SocketAddr = match upstream {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = UdpSocket::bind(bind).await?;
+            socket.connect(upstream).await?;
+            socket.send(query).await?;
+            
+            let mut buf = vec![0u8; 4096];
+            match tokio::time::timeout(timeout, socket.recv(&mut buf)).await {
+                Ok(Ok(len)) => {
+                    buf.truncate(len);
+                    return Ok(buf);
+                }
+                Ok(Err(e)) => last_error = e.into(),
+                Err(_) => last_error = anyhow::anyhow!("Upstream {} timed out", upstream),
+            }
+        }
+        Err(last_error)
+    }
+    
+    // 通过 TCP 依次尝试上游服务器，用于应答较大或被截断的查询
+    async fn forward_tcp(&self, query: &[u8]) -> Result<Vec<u8>> {
+        let timeout = Duration::from_millis(self.forwarder.timeout);
+        let mut last_error = anyhow::anyhow!("No upstream DNS servers");
+        for upstream in &self.forwarder.upstream {
+            let exchange = async {
+                let mut stream = TcpStream::connect(upstream).await?;
+                write_tcp_message(&mut stream, query).await?;
+                let length = stream.read_u16().await?;
+                let mut response = vec![0u8; length as usize];
+                stream.read_exact(&mut response).await?;
+                Ok::<_, anyhow::Error>(response)
+            };
+            match tokio::time::timeout(timeout, exchange).await {
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(e)) => last_error = e,
+                Err(_) => last_error = anyhow::anyhow!("Upstream {} timed out", upstream),
+            }
+        }
+        Err(last_error)
+    }
+    
+    // 从完整域名开始逐级去掉最左边的标签，匹配配置的域名及其子域名
+    fn match_domain(&self, name: &str) -> Option<&Vec<(Family, String)>> {
+        let mut suffix = name;
+        loop {
+            if let Some(sets) = self.targets.get(suffix) {
+                return Some(sets);
+            }
+            suffix = suffix.split_once('.')?.1;
+        }
+    }
+}
+
+async fn write_tcp_message(stream: &mut TcpStream, message: &[u8]) -> Result<()> {
+    let length = u16::try_from(message.len())
+        .map_err(|_| anyhow::anyhow!("DNS message too large: {} bytes", message.len()))?;
+    let mut framed = Vec::with_capacity(message.len() + 2);
+    framed.extend_from_slice(&length.to_be_bytes());
+    framed.extend_from_slice(message);
+    stream.write_all(&framed).await?;
+    Ok(())
+}
+
+// 报头中的 TC 标志
+fn is_truncated(packet: &[u8]) -> bool {
+    packet.get(2).is_some_and(|flags| flags & 0x02 != 0)
+}
+
+// 客户端能接收的 UDP 应答大小：附加部分的 OPT 记录的 CLASS 字段，没有 EDNS 时为 512
+fn udp_payload_limit(query: &[u8]) -> usize {
+    let limit = || {
+        let mut pos = 12;
+        for _ in 0..read_u16(query, 4)? {
+            pos = read_name(query, pos)?.1 + 4;
+        }
+        let records = usize::from(read_u16(query, 6)?)
+            + usize::from(read_u16(query, 8)?)
+            + usize::from(read_u16(query, 10)?);
+        for _ in 0..records {
+            let (_, next) = read_name(query, pos)?;
+            if read_u16(query, next)? == TYPE_OPT {
+                return Some(usize::from(read_u16(query, next + 2)?));
+            }
+            pos = next + 10 + usize::from(read_u16(query, next + 8)?);
+        }
+        None
+    };
+    limit().unwrap_or(DEFAULT_UDP_PAYLOAD).max(DEFAULT_UDP_PAYLOAD)
+}
+
+// 查询中的第一个问题的域名(小写，不带末尾的点)
+fn parse_question(packet: &[u8]) -> Option<String> {
+    if read_u16(packet, 4)? == 0 {
+        return None;
+    }
+    let (name, _) = read_name(packet, 12)?;
+    Some(name.to_ascii_lowercase())
+}
+
+// 应答部分中的 A/AAAA 记录及其 TTL，包括 CNAME 链末端的地址
+fn parse_answers(packet: &[u8]) -> Vec<(IpAddr, u32)> {
+    let mut answers = Vec::new();
+    let (Some(questions), Some(count)) = (read_u16(packet, 4), read_u16(packet, 6)) else {
+        return answers;
+    };
+    
+    let mut pos = 12;
+    for _ in 0..questions {
+        let Some((_, next)) = read_name(packet, pos) else {
+            return answers;
+        };
+        pos = next + 4;
+    }
+    for _ in 0..count {
+        let Some((_, next)) = read_name(packet, pos) else {
+            break;
+        };
+        let (Some(rtype), Some(ttl_high), Some(ttl_low), Some(length)) = (
+            read_u16(packet, next),
+            read_u16(packet, next + 4),
+            read_u16(packet, next + 6),
+            read_u16(packet, next + 8),
+        ) else {
+            break;
+        };
+        let ttl = u32::from(ttl_high) << 16 | u32::from(ttl_low);
+        let data = next + 10;
+        let Some(rdata) = packet.get(data..data + length as usize) else {
+            break;
+        };
+        match (rtype, rdata.len()) {
+            (TYPE_A, 4) => {
+                let octets: [u8; 4] = rdata.try_into().unwrap_or_default();
+                answers.push((IpAddr::from(octets), ttl));
+            }
+            (TYPE_AAAA, 16) => {
+                let octets: [u8; 16] = rdata.try_into().unwrap_or_default();
+                answers.push((IpAddr::from(octets), ttl));
+            }
+            _ => {}
+        }
+        pos = data + length as usize;
+    }
+    answers
+}
+
+// 读取域名，支持压缩指针，返回域名和名称之后的位置
+fn read_name(packet: &[u8], start: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut pos = start;
+    let mut end = None;
+    // 限制跳转次数，防止恶意报文中的指针循环
+    for _ in 0..128 {
+        let length = *packet.get(pos)? as usize;
+        match length {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xc0 == 0xc0 => {
+                let offset = (l & 0x3f) << 8 | *packet.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = offset;
+            }
+            l => {
+                let label = packet.get(pos + 1..pos + 1 + l)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l;
+            }
+        }
+    }
+    None
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*packet.get(pos)?, *packet.get(pos + 1)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // 报头 + 问题 "a.cn" A IN
+    fn message(flags: u8, answers: u16, additional: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x81 | flags, 0x80, 0, 1];
+        packet.extend_from_slice(&answers.to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&additional.to_be_bytes());
+        packet.extend_from_slice(&[1, b'a', 2, b'c', b'n', 0, 0, 1, 0, 1]);
+        packet
+    }
+    
+    // 名称为指向问题的压缩指针的记录
+    fn record(rtype: u16, ttl: u32, rdata: &[u8], length: u16) -> Vec<u8> {
+        let mut record = vec![0xc0, 12];
+        record.extend_from_slice(&rtype.to_be_bytes());
+        record.extend_from_slice(&[0, 1]);
+        record.extend_from_slice(&ttl.to_be_bytes());
+        record.extend_from_slice(&length.to_be_bytes());
+        record.extend_from_slice(rdata);
+        record
+    }
+    
+    #[test]
+    fn read_name_follows_compression_pointer() {
+        let mut packet = message(0, 0, 0);
+        let pointer = packet.len();
+        packet.extend_from_slice(&[3, b'w', b'w', b'w', 0xc0, 12]);
+        assert_eq!(read_name(&packet, 12), Some(("a.cn".to_string(), 18)));
+        assert_eq!(read_name(&packet, pointer), Some(("www.a.cn".to_string(), pointer + 6)));
+        assert_eq!(parse_question(&packet).as_deref(), Some("a.cn"));
+    }
+    
+    #[test]
+    fn read_name_rejects_compression_loop() {
+        let mut packet = message(0, 0, 0);
+        let pointer = packet.len();
+        // 指向自身的指针，以及两个互相指向的指针
+        packet.extend_from_slice(&[0xc0, pointer as u8]);
+        packet.extend_from_slice(&[0xc0, pointer as u8 + 4, 0xc0, pointer as u8 + 2]);
+        assert_eq!(read_name(&packet, pointer), None);
+        assert_eq!(read_name(&packet, pointer + 2), None);
+    }
+    
+    #[test]
+    fn read_name_rejects_out_of_range_offsets() {
+        let mut packet = message(0, 0, 0);
+        let pointer = packet.len();
+        packet.extend_from_slice(&[0xc0, 0xff]);
+        assert_eq!(read_name(&packet, pointer), None);
+        // 标签长度超出报文
+        packet.extend_from_slice(&[10, b'a']);
+        assert_eq!(read_name(&packet, pointer + 2), None);
+        assert_eq!(read_name(&packet, packet.len()), None);
+        assert_eq!(read_name(&packet, usize::MAX), None);
+    }
+    
+    #[test]
+    fn parse_answers_reads_a_and_aaaa_records() {
+        let mut packet = message(0, 3, 0);
+        packet.extend(record(5, 60, &[0xc0, 12], 2));
+        packet.extend(record(TYPE_A, 300, &[1, 2, 3, 4], 4));
+        let v6: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        packet.extend(record(TYPE_AAAA, 120, &v6.octets(), 16));
+        assert_eq!(parse_answers(&packet), vec![
+            (IpAddr::from([1, 2, 3, 4]), 300),
+            (IpAddr::from(v6), 120),
+        ]);
+    }
+    
+    #[test]
+    fn parse_answers_stops_at_truncated_rdata() {
+        let mut packet = message(0, 2, 0);
+        packet.extend(record(TYPE_A, 300, &[1, 2, 3, 4], 4));
+        // 声明 16 字节 RDATA，报文中只有 4 字节
+        packet.extend(record(TYPE_AAAA, 300, &[0x20, 0x01, 0x0d, 0xb8], 16));
+        assert_eq!(parse_answers(&packet), vec![(IpAddr::from([1, 2, 3, 4]), 300)]);
+        
+        // 记录头不完整
+        let mut packet = message(0, 1, 0);
+        packet.extend_from_slice(&[0xc0, 12, 0, 1]);
+        assert!(parse_answers(&packet).is_empty());
+        assert!(parse_answers(&packet[..7]).is_empty());
+    }
+    
+    #[test]
+    fn parse_answers_ignores_mismatched_rdata_length() {
+        let mut packet = message(0, 2, 0);
+        packet.extend(record(TYPE_A, 300, &[1, 2, 3, 4, 5], 5));
+        packet.extend(record(TYPE_A, 300, &[5, 6, 7, 8], 4));
+        assert_eq!(parse_answers(&packet), vec![(IpAddr::from([5, 6, 7, 8]), 300)]);
+    }
+    
+    #[test]
+    fn udp_payload_limit_reads_edns_size() {
+        assert_eq!(udp_payload_limit(&message(0, 0, 0)), DEFAULT_UDP_PAYLOAD);
+        
+        let mut query = message(0, 0, 1);
+        query.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(udp_payload_limit(&query), 1232);
+        
+        // 小于 512 的声明按 512 处理
+        let mut query = message(0, 0, 1);
+        query.extend_from_slice(&[0, 0, 41, 0, 100, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(udp_payload_limit(&query), DEFAULT_UDP_PAYLOAD);
+    }
+    
+    #[test]
+    fn truncated_flag() {
+        assert!(is_truncated(&message(0x02, 0, 0)));
+        assert!(!is_truncated(&message(0, 0, 0)));
+        assert!(!is_truncated(&[]));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use anyhow::Result;
+
+use crate::config::{Config, Family, NftablesSet};
+use crate::fsutil::write_atomic_async;
+
+// 域名 -> 解析结果要写入的集合(地址族, 集合名)
+pub type DomainTargets = BTreeMap<String, Vec<(Family, String)>>;
+
+// 读取集合配置的域名，包括域名列表文件中的域名
+pub async fn load_domains(set: &NftablesSet) -> Result<Vec<String>> {
+    let mut domains: Vec<String> = set.domains.iter()
+        .filter_map(|domain| normalize(domain))
+        .collect();
+    for path in &set.domain_files {
+        let content = tokio::fs::read_to_string(path).await
+            .map_err(|e| anyhow::anyhow!("Failed to read domain list {}: {}", path, e))?;
+        domains.extend(content.lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .filter_map(normalize));
+    }
+    domains.sort();
+    domains.dedup();
+    Ok(domains)
+}
+
+// 统一为小写，去掉 "*." 前缀和末尾的点，不是合法域名时返回 None
+fn normalize(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_start_matches("*.").trim_matches('.').to_ascii_lowercase();
+    let valid = !domain.is_empty()
+        && domain.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'));
+    valid.then_some(domain)
+}
+
+// 汇总所有域名集合，同一域名可以同时写入 IPv4 和 IPv6 集合
+pub async fn domain_targets(config: &Config) -> Result<DomainTargets> {
+    let mut targets = DomainTargets::new();
+    for set in config.interfaces.iter().flat_map(|i| &i.nftables_sets) {
+        let Some(family) = set.family.filter(|_| set.has_domains()) else {
+            continue;
+        };
+        for domain in load_domains(set).await? {
+            targets.entry(domain).or_default().push((family, set.name.clone()));
+        }
+    }
+    Ok(targets)
+}
+
+// dnsmasq: nftset=/example.com/4#inet#mwan3#set4,6#inet#mwan3#set6
+pub fn render_dnsmasq(targets: &DomainTargets, table: &str) -> String {
+    let mut content = String::from("# 由 mwan3-nft 生成，请勿手工修改\n");
+    for (domain, sets) in targets {
+        let sets: Vec<String> = sets.iter()
+            .map(|(family, set)| format!("{}#inet#{}#{}", family_number(*family), table, set))
+            .collect();
+        content.push_str(&format!("nftset=/{}/{}\n", domain, sets.join(",")));
+    }
+    content
+}
+
+// smartdns: nftset /example.com/#4:inet#mwan3#set4,#6:inet#mwan3#set6
+pub fn render_smartdns(targets: &DomainTargets, table: &str) -> String {
+    let mut content = String::from("# 由 mwan3-nft 生成，请勿手工修改\n");
+    for (domain, sets) in targets {
+        let sets: Vec<String> = sets.iter()
+            .map(|(family, set)| format!("#{}:inet#{}#{}", family_number(*family), table, set))
+            .collect();
+        content.push_str(&format!("nftset /{}/{}\n", domain, sets.join(",")));
+    }
+    content
+}
+
+fn family_number(family: Family) -> u8 {
+    match family {
+        Family::V4 => 4,
+        Family::V6 => 6,
+    }
+}
+
+// 写入配置的 DNS 配置片段，返回 (路径, 是否变化)；内容未变化时不改写
+pub async fn write_fragments(config: &Config, table: &str) -> Result<Vec<(String, bool)>> {
+    let fragments = &config.global.dns_fragments;
+    if fragments.dnsmasq.is_none() && fragments.smartdns.is_none() {
+        return Ok(Vec::new());
+    }
+    
+    let targets = domain_targets(config).await?;
+    let outputs = [
+        (&fragments.dnsmasq, render_dnsmasq as fn(&DomainTargets, &str) -> String),
+        (&fragments.smartdns, render_smartdns),
+    ];
+    let mut results = Vec::new();
+    for (path, render) in outputs {
+        let Some(path) = path else {
+            continue;
+        };
+        let content = render(&targets, table);
+        let changed = tokio::fs::read_to_string(path).await.ok().as_ref() != Some(&content);
+        if changed {
+            write_atomic_async(path, content).await?;
+        }
+        results.push((path.clone(), changed));
+    }
+    Ok(results)
+}
+
+// 处理 gen-dns 子命令：指定格式时输出到标准输出，否则写入配置的文件
+pub async fn run_command(config: &Config, table: &str, matches: &clap::ArgMatches) -> Result<()> {
+    if let Some(format) = matches.get_one::<String>("format") {
+        let targets = domain_targets(config).await?;
+        let content = match format.as_str() {
+            "dnsmasq" => render_dnsmasq(&targets, table),
+            "smartdns" => render_smartdns(&targets, table),
+            _ => return Err(anyhow::anyhow!("Unknown DNS format: {}", format)),
+        };
+        print!("{}", content);
+        return Ok(());
+    }
+    
+    let results = write_fragments(config, table).await?;
+    if results.is_empty() {
+        return Err(anyhow::anyhow!("No dns-fragments configured, use --format to print to stdout"));
+    }
+    for (path, changed) in results {
+        println!("{}{}", path, if changed { "" } else { " (未变化)" });
+    }
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+
+// 先写同目录下的临时文件再重命名，读取方(守护进程、DNS 服务器)不会读到写了一半的文件；
+// 上级目录不存在时自动创建
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, path)
+        .map_err(|e| anyhow::anyhow!("Failed to replace {}: {}", path.display(), e))
+}
+
+// 在阻塞线程池中执行 write_atomic，供异步代码使用
+pub async fn write_atomic_async(path: impl AsRef<Path>, content: String) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    tokio::task::spawn_blocking(move || write_atomic(&path, &content)).await?
+}
\ No newline at end of file
@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::time::interval;
 use anyhow::Result;
 
-use crate::config::{Config, Interface};
+use crate::config::{Config, Family, HealthCheckConfig, Interface};
 
 #[derive(Debug, Clone)]
 pub struct InterfaceHealth {
@@ -14,11 +14,71 @@ pub struct InterfaceHealth {
     pub last_check: Instant,
     pub failure_count: u32,
     pub recovery_count: u32,
+    // 最近若干次检测结果，None 表示检测失败
+    pub history: VecDeque<Option<Duration>>,
+}
+
+// 用于统计丢包率和平均延迟的检测窗口大小
+const HISTORY_SIZE: usize = 20;
+
+impl InterfaceHealth {
+    pub fn new() -> Self {
+        Self {
+            is_online: false,
+            latency: None,
+            last_check: Instant::now(),
+            failure_count: 0,
+            recovery_count: 0,
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+        }
+    }
+    
+    // 记录一次检测结果并更新在线状态
+    pub fn record_check(&mut self, latency: Option<Duration>, thresholds: &HealthCheckConfig) {
+        self.last_check = Instant::now();
+        self.latency = latency;
+        if self.history.len() >= HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(latency);
+        
+        // 连续成功/失败达到阈值后才切换状态，避免抖动
+        if latency.is_some() {
+            self.failure_count = 0;
+            self.recovery_count = self.recovery_count.saturating_add(1);
+            if !self.is_online && self.recovery_count >= thresholds.succ_threshold {
+                self.is_online = true;
+            }
+        } else {
+            self.recovery_count = 0;
+            self.failure_count = self.failure_count.saturating_add(1);
+            if self.is_online && self.failure_count >= thresholds.fail_threshold {
+                self.is_online = false;
+            }
+        }
+    }
+    
+    pub fn loss_rate(&self) -> f64 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        let failed = self.history.iter().filter(|r| r.is_none()).count();
+        failed as f64 / self.history.len() as f64
+    }
+    
+    pub fn average_latency(&self) -> Option<Duration> {
+        let samples: Vec<Duration> = self.history.iter().flatten().copied().collect();
+        if samples.is_empty() {
+            return None;
+        }
+        Some(samples.iter().sum::<Duration>() / samples.len() as u32)
+    }
 }
 
 pub struct HealthChecker {
     config: Arc<RwLock<Config>>,
-    interface_health: Arc<RwLock<HashMap<String, InterfaceHealth>>>,
+    // IPv4 和 IPv6 分别检测，同一接口两个地址族的状态可能不同
+    interface_health: Arc<RwLock<HashMap<Family, HashMap<String, InterfaceHealth>>>>,
 }
 
 impl HealthChecker {
@@ -47,36 +107,41 @@ impl HealthChecker {
         
         for interface in interfaces {
             if interface.enabled {
-                self.check_interface(&interface).await?;
+                self.check_interface(&interface, Family::V4).await?;
+                if interface.ipv6 {
+                    self.check_interface(&interface, Family::V6).await?;
+                }
             }
         }
         
         Ok(())
     }
     
-    async fn check_interface(&self, interface: &Interface) -> Result<()> {
+    async fn check_interface(&self, interface: &Interface, family: Family) -> Result<()> {
         // 健康检测实现占位
-        let latency = self.perform_health_check(interface).await?;
+        let latency = self.perform_health_check(interface, family).await?;
         
-        let mut health_map = self.interface_health.write().await;
-        let health = health_map.entry(interface.name.clone()).or_insert(InterfaceHealth {
-            is_online: false,
-            latency: None,
-            last_check: Instant::now(),
-            failure_count: 0,
-            recovery_count: 0,
-        });
-        
-        health.last_check = Instant::now();
-        health.latency = latency;
+        let thresholds = self.config.read().await.global.health_check.clone();
         
-        // 更新健康状态逻辑占位
-        self.update_health_status(health, latency.is_some()).await;
+        let mut health_map = self.interface_health.write().await;
+        let health = health_map.entry(family).or_default()
+            .entry(interface.name.clone())
+            .or_insert_with(InterfaceHealth::new);
+        let was_online = health.is_online;
+        health.record_check(latency, &thresholds);
+        if health.is_online != was_online {
+            tracing::info!(
+                "接口 {} {}{}",
+                interface.name,
+                if family == Family::V6 { "IPv6 " } else { "" },
+                if health.is_online { "已上线" } else { "已下线" }
+            );
+        }
         
         Ok(())
     }
     
-    async fn perform_health_check(&self, interface: &Interface) -> Result<Option<Duration>> {
+    async fn perform_health_check(&self, interface: &Interface, family: Family) -> Result<Option<Duration>> {
         // HTTP健康检测实现占位
         let config = self.config.read().await;
         let url = config.global.health_check.url.clone();
@@ -87,7 +152,8 @@ impl HealthChecker {
         
         // 使用curl命令进行HTTP检测占位
         let output = tokio::process::Command::new("curl")
-            .args(&[
+            .args([
+                if family == Family::V6 { "-6" } else { "-4" },
                 "-s",
                 "-o", "/dev/null",
                 "-w", "%{http_code}",
@@ -113,18 +179,25 @@ impl HealthChecker {
         }
     }
     
-    async fn update_health_status(&self, health: &mut InterfaceHealth, check_success: bool) {
-        // 健康状态更新逻辑占位
-    }
-    
+    #[allow(dead_code)]
     pub async fn get_interface_health(&self, name: &str) -> Option<InterfaceHealth> {
         let health_map = self.interface_health.read().await;
-        health_map.get(name).cloned()
+        health_map.get(&Family::V4)?.get(name).cloned()
+    }
+    
+    pub async fn snapshot(&self, family: Family) -> HashMap<String, InterfaceHealth> {
+        self.interface_health.read().await
+            .get(&family)
+            .cloned()
+            .unwrap_or_default()
     }
     
+    #[allow(dead_code)]
     pub async fn get_online_interfaces(&self) -> Vec<String> {
         let health_map = self.interface_health.read().await;
-        health_map.iter()
+        health_map.get(&Family::V4)
+            .into_iter()
+            .flatten()
             .filter(|(_, health)| health.is_online)
             .map(|(name, _)| name.clone())
             .collect()
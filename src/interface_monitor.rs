@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::process::Command;
 use anyhow::Result;
@@ -6,6 +7,9 @@ use anyhow::Result;
 use crate::config::Config;
 use crate::load_balancer::LoadBalancer;
 
+const ADDRESS_EVENT_DEBOUNCE: Duration = Duration::from_millis(500);
+const MONITOR_RESTART_DELAY: Duration = Duration::from_secs(5);
+
 pub struct InterfaceMonitor {
     config: Arc<RwLock<Config>>,
     load_balancer: Arc<LoadBalancer>,
@@ -20,14 +24,59 @@ impl InterfaceMonitor {
     }
     
     pub async fn start(&self) -> Result<()> {
-        // 启动接口监控占位
-        self.monitor_interfaces().await
+        // 链路和地址监控互相独立，任一个退出后单独重启，不影响另一个
+        let links = async {
+            loop {
+                match self.monitor_interfaces().await {
+                    Ok(()) => tracing::warn!("接口状态监控已退出，稍后重启"),
+                    Err(e) => tracing::error!("接口状态监控失败: {}，稍后重启", e),
+                }
+                tokio::time::sleep(MONITOR_RESTART_DELAY).await;
+            }
+        };
+        let addresses = async {
+            loop {
+                match self.monitor_addresses().await {
+                    Ok(()) => tracing::warn!("地址变化监控已退出，稍后重启"),
+                    Err(e) => tracing::error!("地址变化监控失败: {}，稍后重启", e),
+                }
+                tokio::time::sleep(MONITOR_RESTART_DELAY).await;
+            }
+        };
+        tokio::join!(links, addresses);
+        Ok(())
+    }
+    
+    // 地址或路由变化时重新同步规则，更新直连网段
+    async fn monitor_addresses(&self) -> Result<()> {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+        
+        let mut cmd = Command::new("ip")
+            .args(["monitor", "address", "route"])
+            .kill_on_drop(true)
+            .stdout(std::process::Stdio::piped())
+            .spawn()?;
+        let Some(stdout) = cmd.stdout.take() else {
+            return Ok(());
+        };
+        
+        let mut lines = BufReader::new(stdout).lines();
+        while lines.next_line().await?.is_some() {
+            // 地址变化通常成批出现，合并短时间内的事件后再同步
+            while let Ok(Ok(Some(_))) = tokio::time::timeout(ADDRESS_EVENT_DEBOUNCE, lines.next_line()).await {}
+            if let Err(e) = self.load_balancer.reconcile().await {
+                tracing::error!("地址变化后同步规则失败: {}", e);
+            }
+        }
+        
+        Ok(())
     }
     
     async fn monitor_interfaces(&self) -> Result<()> {
         // 使用 ip monitor link 监控接口状态变化占位
         let mut cmd = Command::new("ip")
-            .args(&["monitor", "link"])
+            .args(["monitor", "link"])
+            .kill_on_drop(true)
             .stdout(std::process::Stdio::piped())
             .spawn()?;
         
@@ -45,41 +94,116 @@ impl InterfaceMonitor {
         let reader = BufReader::new(stdout);
         let mut lines = reader.lines();
         
+        // 单个事件处理失败只记录日志，继续处理后续事件
         while let Some(line) = lines.next_line().await? {
-            self.parse_interface_event(&line).await?;
+            if let Err(e) = self.parse_interface_event(&line).await {
+                tracing::error!("处理接口事件失败: {}", e);
+            }
         }
         
         Ok(())
     }
     
     async fn parse_interface_event(&self, line: &str) -> Result<()> {
-        // 解析接口事件占位
-        // 示例: "2: eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500"
-        
-        if line.contains("UP") {
-            // 接口上线
-            if let Some(interface_name) = self.extract_interface_name(line) {
-                self.load_balancer.handle_interface_change(&interface_name, true).await?;
-            }
-        } else if line.contains("DOWN") {
-            // 接口下线
-            if let Some(interface_name) = self.extract_interface_name(line) {
-                self.load_balancer.handle_interface_change(&interface_name, false).await?;
-            }
+        let Some((interface_name, is_online)) = parse_link_event(line) else {
+            return Ok(());
+        };
+        // 只处理配置中的接口，忽略 veth、网桥等其他接口的事件
+        let managed = self.config.read().await.interfaces.iter()
+            .any(|i| i.interface_name == interface_name);
+        if managed {
+            self.load_balancer.handle_interface_change(&interface_name, is_online).await?;
         }
         
         Ok(())
     }
+}
+
+// 解析 ip monitor link 的一行输出，返回接口名和链路是否可用，
+// 如 "2: eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc fq_codel state UP ..."
+// 或 "Deleted 14: veth0@if13: <BROADCAST,MULTICAST> mtu 1500 ... state DOWN ..."，
+// 以空白开头的续行(link/ether 等)不是事件
+fn parse_link_event(line: &str) -> Option<(String, bool)> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (deleted, line) = match line.strip_prefix("Deleted ") {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    
+    let mut parts = line.split_whitespace();
+    parts.next()?.strip_suffix(':')?.parse::<u32>().ok()?;
+    let name = parts.next()?.strip_suffix(':')?;
+    // veth 等接口带有对端后缀，如 "veth0@if13"
+    let name = name.split('@').next().filter(|n| !n.is_empty())?.to_string();
+    if deleted {
+        return Some((name, false));
+    }
     
-    fn extract_interface_name(&self, line: &str) -> Option<String> {
-        // 从监控输出中提取接口名称占位
-        // 简单的解析逻辑
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 2 {
-            let name = parts[1].trim_end_matches(':');
-            Some(name.to_string())
-        } else {
-            None
+    let fields: Vec<&str> = parts.collect();
+    let state = fields.iter()
+        .position(|f| *f == "state")
+        .and_then(|i| fields.get(i + 1));
+    let is_online = match state {
+        Some(&"UP") => true,
+        Some(&"DOWN") | Some(&"LOWERLAYERDOWN") | Some(&"NOTPRESENT") => false,
+        // PPP、WireGuard 等接口的状态为 UNKNOWN，按标志位判断
+        _ => {
+            let flags = fields.first()?
+                .strip_prefix('<')?
+                .strip_suffix('>')?;
+            let flags: Vec<&str> = flags.split(',').collect();
+            flags.contains(&"UP") && flags.contains(&"LOWER_UP")
         }
+    };
+    Some((name, is_online))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn link_state_field_decides_status() {
+        assert_eq!(
+            parse_link_event("2: eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc fq_codel state UP mode DEFAULT group default qlen 1000"),
+            Some(("eth0".to_string(), true))
+        );
+        // 标志中的 UP 不代表链路可用
+        assert_eq!(
+            parse_link_event("2: eth0: <NO-CARRIER,BROADCAST,MULTICAST,UP> mtu 1500 qdisc fq_codel state DOWN mode DEFAULT group default qlen 1000"),
+            Some(("eth0".to_string(), false))
+        );
+        assert_eq!(
+            parse_link_event("5: lan1@eth0: <BROADCAST,MULTICAST,UP> mtu 1500 qdisc noqueue state LOWERLAYERDOWN mode DEFAULT"),
+            Some(("lan1".to_string(), false))
+        );
+    }
+    
+    #[test]
+    fn unknown_state_falls_back_to_flags() {
+        assert_eq!(
+            parse_link_event("9: pppoe-wan: <POINTOPOINT,MULTICAST,NOARP,UP,LOWER_UP> mtu 1492 qdisc fq_codel state UNKNOWN mode DEFAULT"),
+            Some(("pppoe-wan".to_string(), true))
+        );
+        assert_eq!(
+            parse_link_event("9: pppoe-wan: <POINTOPOINT,MULTICAST,NOARP> mtu 1492 qdisc noop state UNKNOWN mode DEFAULT"),
+            Some(("pppoe-wan".to_string(), false))
+        );
+    }
+    
+    #[test]
+    fn deleted_interface_is_down() {
+        assert_eq!(
+            parse_link_event("Deleted 14: veth0@if13: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc noqueue state UP mode DEFAULT"),
+            Some(("veth0".to_string(), false))
+        );
+    }
+    
+    #[test]
+    fn continuation_lines_are_ignored() {
+        assert_eq!(parse_link_event("    link/ether 00:11:22:33:44:55 brd ff:ff:ff:ff:ff:ff"), None);
+        assert_eq!(parse_link_event(""), None);
     }
 }
\ No newline at end of file
@@ -1,118 +1,955 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use chrono::Local;
+use ipnet::IpNet;
+use tokio::sync::{Mutex, RwLock};
 use anyhow::Result;
 
-use crate::config::{Config, Policy};
-use crate::health_check::HealthChecker;
+use crate::config::{parse_size, AdaptiveConfig, BalanceHash, Config, Family, Interface, Policy, PolicyMember, QuotaAction, SetMatch};
+use crate::connected;
+use crate::conntrack;
+use crate::health_check::{HealthChecker, InterfaceHealth};
 use crate::nftables::NftablesManager;
+use crate::overrides::{drain_of, forced_member, is_excluded, Override, OverrideKind, OverrideStore};
+use crate::quota::QuotaTracker;
+use crate::resolv;
+use crate::schedule;
+use crate::sets::{SetGenerator, SetLoader};
+use crate::stats::StatsCollector;
+use crate::status::{InterfaceStatus, StatusReport, TargetStatus};
+
+// 配置权重放大倍数，便于自适应系数细粒度调整；分流按权重比例分配槽位，显示时除回该倍数
+pub const WEIGHT_SCALE: u32 = 100;
+
+// 策略解析后的出口接口及其权重
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteTarget {
+    pub interface: String,
+    pub mark: u32,
+    pub weight: u32,
+}
+
+impl RouteTarget {
+    // 按配置单位显示的权重
+    pub fn display_weight(&self) -> f64 {
+        f64::from(self.weight) / f64::from(WEIGHT_SCALE)
+    }
+}
+
+// 策略解析结果：出口接口列表及其分流方式
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyDecision {
+    // 产生该决策的策略名称
+    pub policy: String,
+    pub family: Family,
+    pub targets: Vec<RouteTarget>,
+    pub hash: BalanceHash,
+    // 会话保持超时时间(秒)
+    pub sticky: Option<u64>,
+    // 按源地址固定出口的覆盖规则
+    pub pins: Vec<SourcePin>,
+    // 匹配接口绑定集合的流量直接走该接口
+    pub sets: Vec<SetRoute>,
+    // 当前可用的接口，固定出口和集合规则经由 vmap 分派到这些接口
+    pub routes: Vec<InterfaceRoute>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePin {
+    pub source: IpNet,
+    pub interface: String,
+    pub mark: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetRoute {
+    pub set: String,
+    pub direction: SetMatch,
+    pub interface: String,
+    pub mark: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceRoute {
+    pub interface: String,
+    pub mark: u32,
+}
+
+// 策略解析所需的运行时状态
+pub struct PolicyContext<'a> {
+    pub config: &'a Config,
+    // 解析的地址族，health 为该地址族的健康状态
+    pub family: Family,
+    pub health: &'a HashMap<String, InterfaceHealth>,
+    // 自适应负载均衡权重系数: 策略名 -> 接口名 -> 系数
+    pub adaptive: &'a HashMap<String, HashMap<String, f64>>,
+    // 当前有效的运行时覆盖规则
+    pub overrides: &'a [Override],
+    // 流量配额已用尽的接口
+    pub quota_exhausted: &'a HashMap<String, QuotaAction>,
+    // 接口绑定集合的地址族，地址族未知(如外部集合尚未创建)的集合不生成规则
+    pub set_families: &'a HashMap<String, Family>,
+}
+
+// 排空中接口的进度
+#[derive(Debug, Clone, Default)]
+struct DrainStatus {
+    flows: Option<usize>,
+    completed: bool,
+}
+
+// 单个策略成员的解析结果
+struct Resolution {
+    targets: Vec<RouteTarget>,
+    latency: Option<Duration>,
+    hash: BalanceHash,
+}
 
 pub struct LoadBalancer {
     config: Arc<RwLock<Config>>,
     health_checker: Arc<HealthChecker>,
-    nftables: NftablesManager,
+    nftables: Arc<NftablesManager>,
     current_policy: Arc<RwLock<Option<String>>>,
+    // 各地址族的策略解析结果
+    current_decisions: Arc<RwLock<Vec<PolicyDecision>>>,
+    applied_script: Arc<RwLock<Option<Vec<String>>>>,
+    adaptive_factors: Arc<RwLock<HashMap<String, HashMap<String, f64>>>>,
+    stats: Arc<RwLock<StatsCollector>>,
+    drains: Arc<RwLock<HashMap<String, DrainStatus>>>,
+    active_schedules: Arc<RwLock<Vec<String>>>,
+    quotas: Arc<RwLock<QuotaTracker>>,
+    // 上一次同步时在线的接口
+    online: Arc<RwLock<HashSet<(Family, String)>>>,
+    // 最近一次从路由表中读取的直连网段
+    connected: Arc<RwLock<Vec<IpNet>>>,
+    // 接口绑定集合的地址族，None 表示集合不存在
+    set_families: Arc<RwLock<HashMap<String, Option<Family>>>>,
+    sets: Arc<RwLock<SetLoader>>,
+    set_generator: Arc<SetGenerator>,
+    // 各接口的 DNS 服务器，发往这些服务器的 DNS 查询固定走该接口
+    dns_servers: Arc<RwLock<HashMap<String, Vec<IpAddr>>>>,
+    // 串行化同步过程，定时器、接口事件和命令行触发的同步不会交错执行
+    reconcile_lock: Mutex<()>,
 }
 
 impl LoadBalancer {
-    pub fn new(config: Arc<RwLock<Config>>, health_checker: Arc<HealthChecker>) -> Self {
+    pub fn new(
+        config: Arc<RwLock<Config>>,
+        health_checker: Arc<HealthChecker>,
+        nftables: Arc<NftablesManager>,
+        set_generator: Arc<SetGenerator>,
+    ) -> Self {
         Self {
             config,
             health_checker,
-            nftables: NftablesManager::new(),
+            nftables,
             current_policy: Arc::new(RwLock::new(None)),
+            current_decisions: Arc::new(RwLock::new(Vec::new())),
+            applied_script: Arc::new(RwLock::new(None)),
+            adaptive_factors: Arc::new(RwLock::new(HashMap::new())),
+            stats: Arc::new(RwLock::new(StatsCollector::default())),
+            drains: Arc::new(RwLock::new(HashMap::new())),
+            active_schedules: Arc::new(RwLock::new(Vec::new())),
+            quotas: Arc::new(RwLock::new(QuotaTracker::default())),
+            online: Arc::new(RwLock::new(HashSet::new())),
+            connected: Arc::new(RwLock::new(Vec::new())),
+            set_families: Arc::new(RwLock::new(HashMap::new())),
+            sets: Arc::new(RwLock::new(SetLoader::default())),
+            set_generator,
+            dns_servers: Arc::new(RwLock::new(HashMap::new())),
+            reconcile_lock: Mutex::new(()),
         }
     }
     
     pub async fn start(&self) -> Result<()> {
-        // 负载均衡器启动占位
         tracing::info!("负载均衡器已启动");
         
-        // 这里可以添加定期检查和更新负载均衡策略的逻辑
+        let config = self.config.read().await;
+        let mut interval = tokio::time::interval(Duration::from_secs(config.global.reconcile_interval));
+        drop(config);
+        
+        // interval 首次 tick 立即返回，启动时即安装规则并应用默认策略；
+        // 表被外部修改时立即重新同步，网段列表重新生成后立即更新集合
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
-            // 定期检查和应用负载均衡策略占位
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.nftables.table_changed() => {
+                    let guard = self.reconcile_lock.lock().await;
+                    let drifted = self.check_drift().await;
+                    drop(guard);
+                    if !drifted {
+                        continue;
+                    }
+                }
+                _ = self.set_generator.generated() => {
+                    let _guard = self.reconcile_lock.lock().await;
+                    self.update_sets().await;
+                    continue;
+                }
+            }
+            if let Err(e) = self.reconcile().await {
+                tracing::error!("规则同步失败: {}", e);
+            }
+        }
+    }
+    
+    // 根据最新的配置和健康状态同步规则，返回是否重新应用了规则
+    pub async fn reconcile(&self) -> Result<bool> {
+        let _guard = self.reconcile_lock.lock().await;
+        self.update_stats().await;
+        self.update_connected().await;
+        self.update_dns_servers().await;
+        self.update_set_families().await;
+        self.update_adaptive_weights().await;
+        if let Err(e) = self.update_quotas().await {
+            tracing::warn!("更新流量配额失败: {}", e);
+        }
+        let (policy_name, policy_v6_name) = self.update_schedules().await;
+        self.check_drift().await;
+        let changed = self.apply_policy(&policy_name, &policy_v6_name).await?;
+        // 受管集合由规则集创建，规则应用后再填充元素
+        self.update_sets().await;
+        self.flush_failed_interfaces().await;
+        self.update_drains().await;
+        if let Err(e) = self.write_status().await {
+            tracing::warn!("写入状态文件失败: {}", e);
+        }
+        Ok(changed)
+    }
+    
+    // 新规则生效后清除刚下线接口的连接，客户端重连时即走其他接口
+    async fn flush_failed_interfaces(&self) {
+        let mut online = HashSet::new();
+        for family in Family::ALL {
+            online.extend(self.health_checker.snapshot(family).await
+                .into_iter()
+                .filter(|(_, health)| health.is_online)
+                .map(|(name, _)| (family, name)));
+        }
+        let mut previous = self.online.write().await;
+        let failed: Vec<(Family, String)> = previous.difference(&online).cloned().collect();
+        *previous = online;
+        drop(previous);
+        
+        let config = self.config.read().await;
+        for (family, name) in failed {
+            if let Some(interface) = config.find_interface(&name) {
+                flush_interface_flows(&config, interface, Some(family)).await;
+            }
+        }
+    }
+    
+    // 读回已安装的表与上一次应用的规则集比较，被外部修改(如 nft flush ruleset、fw4 重新加载)时
+    // 丢弃已应用的记录，下一次同步重新安装全部规则，返回是否检测到修改
+    async fn check_drift(&self) -> bool {
+        let mut applied = self.applied_script.write().await;
+        let Some(script) = applied.as_ref() else {
+            return false;
+        };
+        let actual = match self.nftables.read_state().await {
+            Ok(actual) => actual,
+            Err(e) => {
+                tracing::debug!("读取 nftables 规则失败: {}", e);
+                return false;
+            }
+        };
+        let expected = self.nftables.expected_state(script);
+        let drift = self.nftables.describe_drift(&expected, actual.as_ref());
+        if drift.is_empty() {
+            return false;
+        }
+        
+        tracing::warn!("检测到 nftables 规则被外部修改，重新安装: {}", drift.join("; "));
+        *applied = None;
+        drop(applied);
+        self.sets.write().await.reset();
+        true
+    }
+    
+    // 读取失败时保留上一次的结果，避免清空直连网段
+    async fn update_connected(&self) {
+        match connected::learn_prefixes().await {
+            Ok(prefixes) => *self.connected.write().await = prefixes,
+            Err(e) => tracing::warn!("读取直连网段失败: {}", e),
+        }
+    }
+    
+    // 拨号后系统获得的 DNS 服务器可能变化，读取失败时保留上一次的结果
+    async fn update_dns_servers(&self) {
+        let config = self.config.read().await;
+        let learned = match resolv::learn_servers(&config.global.resolv_conf).await {
+            Ok(learned) => learned,
+            Err(e) => {
+                tracing::warn!("读取 {} 失败: {}", config.global.resolv_conf, e);
+                return;
+            }
+        };
+        let servers = resolv::interface_servers(&config, &learned);
+        drop(config);
+        
+        let mut current = self.dns_servers.write().await;
+        if *current == servers {
+            return;
+        }
+        for (name, addrs) in &servers {
+            if current.get(name) != Some(addrs) {
+                let addrs: Vec<String> = addrs.iter().map(|addr| addr.to_string()).collect();
+                tracing::info!("接口 {} 的 DNS 服务器: {}", name, addrs.join(", "));
+            }
+        }
+        *current = servers;
+    }
+    
+    // 未指定地址族的集合由外部程序创建，从集合类型中读取地址族，集合不存在时跳过
+    async fn update_set_families(&self) {
+        let config = self.config.read().await;
+        let mut families = self.set_families.write().await;
+        let mut current = HashMap::new();
+        for set in config.interfaces.iter().flat_map(|i| &i.nftables_sets) {
+            let family = match (set.family, families.get(&set.name)) {
+                (Some(family), _) => Some(family),
+                (None, previous) => match self.nftables.set_family(&set.name).await {
+                    Ok(family) => Some(family),
+                    Err(e) => {
+                        if previous != Some(&None) {
+                            tracing::warn!("集合 {} 暂不可用，匹配该集合的流量走策略: {}", set.name, e);
+                        }
+                        None
+                    }
+                },
+            };
+            current.insert(set.name.clone(), family);
+        }
+        *families = current;
+    }
+    
+    async fn update_sets(&self) {
+        let config = self.config.read().await;
+        self.sets.write().await.sync(&config, &self.nftables).await;
+    }
+    
+    async fn update_stats(&self) {
+        let config = self.config.read().await;
+        self.stats.write().await.sample(&config, &self.nftables).await;
+    }
+    
+    async fn update_quotas(&self) -> Result<()> {
+        let config = self.config.read().await;
+        if config.interfaces.iter().all(|i| i.quota.is_none()) {
+            return Ok(());
+        }
+        self.quotas.write().await.update(&config, Local::now()).await
+    }
+    
+    // 记录计划的生效和结束，返回 IPv4 和 IPv6 当前应使用的策略
+    async fn update_schedules(&self) -> (String, String) {
+        let config = self.config.read().await;
+        let now = Local::now();
+        let active: Vec<String> = schedule::active_schedules(&config, now).into_iter()
+            .map(|s| s.name.clone())
+            .collect();
+        
+        let mut previous = self.active_schedules.write().await;
+        for name in active.iter().filter(|name| !previous.contains(name)) {
+            tracing::info!("计划 {} 已生效", name);
+        }
+        for name in previous.iter().filter(|name| !active.contains(name)) {
+            tracing::info!("计划 {} 已结束", name);
+        }
+        *previous = active;
+        
+        (
+            schedule::effective_policy(&config, now, Family::V4).to_string(),
+            schedule::effective_policy(&config, now, Family::V6).to_string(),
+        )
+    }
+    
+    // 跟踪排空中接口的剩余连接，超过截止时间后清除剩余连接
+    async fn update_drains(&self) {
+        let config = self.config.read().await;
+        let overrides = self.load_overrides(&config).await;
+        let mut drains = self.drains.write().await;
+        drains.retain(|name, _| drain_of(&overrides, name).is_some());
+        
+        for interface in &config.interfaces {
+            let Some(deadline) = drain_of(&overrides, &interface.name) else {
+                continue;
+            };
+            let state = drains.entry(interface.name.clone()).or_default();
+            
+            let mark = config.global.nftables.mark_value(interface.mark);
+            let mask = config.global.nftables.mark_mask;
+            let mut flows = match conntrack::count_flows(mark, mask).await {
+                Ok(flows) => Some(flows),
+                Err(e) => {
+                    tracing::warn!("统计接口 {} 的连接数失败: {}", interface.name, e);
+                    None
+                }
+            };
+            
+            if deadline.is_some_and(|d| d <= Local::now()) && flows.is_some_and(|n| n > 0) {
+                match conntrack::flush_mark(mark, mask, None).await {
+                    Ok(()) => {
+                        tracing::info!("接口 {} 排空截止，已清除剩余的 {} 条连接", interface.name, flows.unwrap_or(0));
+                        flows = Some(0);
+                    }
+                    Err(e) => tracing::warn!("清除接口 {} 的连接失败: {}", interface.name, e),
+                }
+            }
+            
+            if flows == Some(0) && !state.completed {
+                tracing::info!("接口 {} 排空完成", interface.name);
+                state.completed = true;
+            }
+            state.flows = flows;
+        }
+    }
+    
+    // 当前有效的运行时覆盖规则，以及生效计划产生的排空规则
+    async fn load_overrides(&self, config: &Config) -> Vec<Override> {
+        let now = Local::now();
+        let mut overrides = match OverrideStore::new(&config.global.state_dir).active(now).await {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                tracing::warn!("读取覆盖规则失败: {}", e);
+                Vec::new()
+            }
+        };
+        overrides.extend(schedule::scheduled_overrides(config, now));
+        overrides
+    }
+    
+    async fn write_status(&self) -> Result<()> {
+        let config = self.config.read().await;
+        let health = self.health_checker.snapshot(Family::V4).await;
+        let health_v6 = self.health_checker.snapshot(Family::V6).await;
+        let decisions = self.current_decisions.read().await;
+        let decision_v6 = decisions.iter()
+            .find(|d| d.family == Family::V6)
+            .filter(|_| config.interfaces.iter().any(|i| i.ipv6));
+        let drains = self.drains.read().await;
+        let quotas = self.quotas.read().await;
+        let stats = self.stats.read().await;
+        let policy = self.current_policy.read().await.clone().unwrap_or_default();
+        
+        let report = StatusReport {
+            updated: Local::now(),
+            connections: stats.policy_connections(&policy),
+            policy,
+            targets: decisions.iter()
+                .filter(|d| d.family == Family::V4)
+                .flat_map(|d| target_status(&d.targets))
+                .collect(),
+            policy_v6: decision_v6.map(|d| d.policy.clone()),
+            targets_v6: decision_v6.map(|d| target_status(&d.targets)).unwrap_or_default(),
+            interfaces: config.interfaces.iter()
+                .map(|i| {
+                    let state = health.get(&i.name);
+                    let traffic = stats.interface(&i.name);
+                    InterfaceStatus {
+                        name: i.name.clone(),
+                        online: state.is_some_and(|h| h.is_online),
+                        online_v6: i.ipv6.then(|| health_v6.get(&i.name).is_some_and(|h| h.is_online)),
+                        latency_ms: state.and_then(|h| h.latency).map(|l| l.as_millis() as u64),
+                        loss: state.map(|h| h.loss_rate()).unwrap_or(0.0),
+                        draining: drains.contains_key(&i.name),
+                        flows: drains.get(&i.name).and_then(|d| d.flows),
+                        quota_used: quotas.usage(&i.name).filter(|_| i.quota.is_some()).map(|u| u.used),
+                        quota_limit: i.quota.as_ref().and_then(|q| parse_size(&q.limit).ok()),
+                        rx_rate: traffic.and_then(|t| t.rx_rate).map(|r| r as u64),
+                        tx_rate: traffic.and_then(|t| t.tx_rate).map(|r| r as u64),
+                        marked_bytes: traffic.and_then(|t| t.marked_bytes),
+                        marked_rate: traffic.and_then(|t| t.marked_rate).map(|r| r as u64),
+                    }
+                })
+                .collect(),
+            overrides: self.load_overrides(&config).await,
+            schedules: self.active_schedules.read().await.clone(),
+        };
+        drop(decisions);
+        drop(drains);
+        drop(quotas);
+        drop(stats);
+        
+        report.save(&config.global.state_dir).await
+    }
+    
+    // 根据健康统计和接口吞吐量重新计算自适应权重系数，返回系数是否发生变化
+    async fn update_adaptive_weights(&self) -> bool {
+        let config = self.config.read().await;
+        if config.policies.iter().all(|p| p.adaptive.is_none()) {
+            return false;
         }
+        
+        let health = self.health_checker.snapshot(Family::V4).await;
+        let throughput = self.stats.read().await.throughput();
+        let mut factors = self.adaptive_factors.write().await;
+        step_adaptive_factors(&config, &health, &throughput, &mut factors)
     }
     
-    pub async fn apply_policy(&self, policy_name: &str) -> Result<()> {
+    async fn apply_policy(&self, policy_name: &str, policy_v6_name: &str) -> Result<bool> {
         let config = self.config.read().await;
-        let policy = config.policies.iter()
-            .find(|p| p.policy_type == policy_name)
-            .ok_or_else(|| anyhow::anyhow!("Policy not found: {}", policy_name))?;
+        let dual_stack = config.interfaces.iter().any(|i| i.ipv6);
         
-        match policy.policy_type.as_str() {
-            "url-test" => self.apply_auto_select_policy(policy).await?,
-            "load-balance" => self.apply_round_robin_policy(policy).await?,
-            "fallback" => self.apply_failover_policy(policy).await?,
-            _ => return Err(anyhow::anyhow!("Unknown policy type: {}", policy.policy_type)),
+        let overrides = self.load_overrides(&config).await;
+        let adaptive = self.adaptive_factors.read().await;
+        let quota_exhausted = self.quotas.read().await.exhausted(&config);
+        let set_families: HashMap<String, Family> = self.set_families.read().await.iter()
+            .filter_map(|(name, family)| Some((name.clone(), (*family)?)))
+            .collect();
+        let mut decisions = Vec::new();
+        for (family, name) in [(Family::V4, policy_name), (Family::V6, policy_v6_name)] {
+            let policy = config.find_policy(name)
+                .ok_or_else(|| anyhow::anyhow!("Policy not found: {}", name))?;
+            let health = self.health_checker.snapshot(family).await;
+            let context = PolicyContext {
+                config: &config,
+                family,
+                health: &health,
+                adaptive: &adaptive,
+                overrides: &overrides,
+                quota_exhausted: &quota_exhausted,
+                set_families: &set_families,
+            };
+            decisions.push(resolve_policy(&context, policy));
         }
+        drop(adaptive);
+        
+        // 期望规则集与已安装的一致时不做任何操作
+        let mut bypass = self.connected.read().await.clone();
+        bypass.extend(config.global.bypass.iter().copied());
+        let dns_servers = self.dns_servers.read().await;
+        let script = self.nftables.render_ruleset(
+            &config.interfaces,
+            &decisions,
+            &IpNet::aggregate(&bypass),
+            &dns_servers,
+        );
+        drop(dns_servers);
+        drop(config);
+        let mut applied = self.applied_script.write().await;
+        if applied.as_ref() == Some(&script) {
+            // 规则不变时只有可用接口或策略出口变化(上下线、权重、覆盖规则)，只更新 map 元素
+            let previous = self.current_decisions.read().await;
+            let updates = self.nftables.render_route_updates(&previous, &decisions);
+            drop(previous);
+            if updates.is_empty() {
+                return Ok(false);
+            }
+            self.nftables.apply_ruleset(&updates).await?;
+        } else {
+            let mut full = script.clone();
+            full.extend(self.nftables.render_routes(&decisions));
+            self.nftables.apply_ruleset(&full).await?;
+            *applied = Some(script);
+        }
+        drop(applied);
+        
+        // 没有接口启用 IPv6 时 IPv6 策略始终为空，不记录日志
+        for decision in decisions.iter().filter(|d| d.family == Family::V4 || dual_stack) {
+            let label = if decision.family == Family::V6 { "IPv6 " } else { "" };
+            if decision.targets.is_empty() {
+                tracing::warn!("{}策略 {} 没有可用的在线接口", label, decision.policy);
+            } else {
+                let targets: Vec<String> = decision.targets.iter()
+                    .map(|t| format!("{}({})", t.interface, t.display_weight()))
+                    .collect();
+                tracing::info!("已应用{}策略 {}: {}", label, decision.policy, targets.join(", "));
+            }
+        }
+        
+        // 不再使用的接口，清除指向它的会话保持记录
+        let mut previous = self.current_decisions.write().await;
+        for decision in &decisions {
+            let removed = previous.iter()
+                .filter(|p| p.family == decision.family)
+                .flat_map(|p| &p.targets)
+                .filter(|target| !decision.targets.iter().any(|t| t.interface == target.interface));
+            for target in removed {
+                if let Err(e) = self.nftables.clear_sticky(decision.family, &target.interface).await {
+                    tracing::warn!("清除接口 {} 的会话保持记录失败: {}", target.interface, e);
+                }
+            }
+        }
+        *previous = decisions;
+        drop(previous);
         
         let mut current = self.current_policy.write().await;
         *current = Some(policy_name.to_string());
         
-        Ok(())
+        Ok(true)
     }
     
-    async fn apply_auto_select_policy(&self, policy: &Policy) -> Result<()> {
-        // 自动选择策略实现占位
-        let online_interfaces = self.health_checker.get_online_interfaces().await;
-        let selected = self.select_best_interface(&online_interfaces).await?;
-        self.update_routing_rules(&selected).await?;
-        Ok(())
+    pub async fn handle_interface_change(&self, interface: &str, is_online: bool) -> Result<()> {
+        tracing::info!("接口 {} 状态变化: {}", interface, if is_online { "上线" } else { "下线" });
+        let result = self.reconcile().await;
+        
+        // 链路断开时不必等待健康检测确认，直接清除该接口的连接，规则同步失败时也要清除
+        if !is_online {
+            let config = self.config.read().await;
+            let matched = config.interfaces.iter()
+                .find(|i| i.name == interface || i.interface_name == interface);
+            if let Some(matched) = matched {
+                flush_interface_flows(&config, matched, None).await;
+            }
+        }
+        result.map(|_| ())
     }
-    
-    async fn apply_round_robin_policy(&self, policy: &Policy) -> Result<()> {
-        // 轮询策略实现占位
-        let online_interfaces = self.health_checker.get_online_interfaces().await;
-        self.setup_round_robin_rules(&online_interfaces, policy).await?;
-        Ok(())
+}
+
+// 清除经下线接口的连接跟踪条目，未开启 flush-conntrack 时保留；
+// 只有一个地址族下线时只清除该地址族的连接
+async fn flush_interface_flows(config: &Config, interface: &Interface, family: Option<Family>) {
+    if !config.global.flush_conntrack {
+        return;
+    }
+    let nftables = &config.global.nftables;
+    match conntrack::flush_mark(nftables.mark_value(interface.mark), nftables.mark_mask, family).await {
+        Ok(()) => tracing::info!("已清除下线接口 {} 的连接跟踪条目", interface.name),
+        Err(e) => tracing::warn!("清除接口 {} 的连接跟踪条目失败: {}", interface.name, e),
     }
+}
+
+// 根据当前健康状态递归解析策略树，得到最终的出口接口及权重
+pub fn resolve_policy(context: &PolicyContext<'_>, policy: &Policy) -> PolicyDecision {
+    let mut resolution = resolve_nested(context, policy);
     
-    async fn apply_failover_policy(&self, policy: &Policy) -> Result<()> {
-        // 故障转移策略实现占位
-        let online_interfaces = self.health_checker.get_online_interfaces().await;
-        let primary = self.select_primary_interface(&online_interfaces, policy).await?;
-        self.setup_failover_rules(&primary).await?;
-        Ok(())
+    // 配额用尽后降级的接口只在没有其他可用接口时使用
+    if resolution.targets.is_empty() && context.quota_exhausted.values().any(|a| *a == QuotaAction::LastResort) {
+        let excluded: HashMap<String, QuotaAction> = context.quota_exhausted.iter()
+            .filter(|(_, action)| **action == QuotaAction::Exclude)
+            .map(|(name, action)| (name.clone(), *action))
+            .collect();
+        let relaxed = PolicyContext {
+            quota_exhausted: &excluded,
+            ..*context
+        };
+        resolution = resolve_nested(&relaxed, policy);
     }
     
-    async fn select_best_interface(&self, interfaces: &[String]) -> Result<String> {
-        // 选择最佳接口逻辑占位
-        interfaces.first()
-            .ok_or_else(|| anyhow::anyhow!("No online interfaces"))
-            .map(|s| s.clone())
+    // 固定出口和集合规则不随接口状态变化，接口不可用时不在 routes 中，匹配的流量回落到正常策略
+    let pins = context.overrides.iter()
+        .filter_map(|o| match &o.kind {
+            OverrideKind::Pin { source, interface } => Some((source, interface)),
+            _ => None,
+        })
+        .filter(|(source, _)| match context.family {
+            Family::V4 => matches!(source, IpNet::V4(_)),
+            Family::V6 => matches!(source, IpNet::V6(_)),
+        })
+        .filter_map(|(source, interface)| {
+            let interface = context.config.find_interface(interface)?;
+            Some(SourcePin {
+                source: *source,
+                interface: interface.name.clone(),
+                mark: interface.mark,
+            })
+        })
+        .collect();
+    
+    let sets = context.config.interfaces.iter()
+        .flat_map(|interface| interface.nftables_sets.iter().map(move |set| (interface, set)))
+        .filter(|(_, set)| context.set_families.get(&set.name) == Some(&context.family))
+        .map(|(interface, set)| SetRoute {
+            set: set.name.clone(),
+            direction: set.direction,
+            interface: interface.name.clone(),
+            mark: interface.mark,
+        })
+        .collect();
+    
+    let routes = context.config.interfaces.iter()
+        .filter(|interface| interface_health(context, interface).is_some())
+        .map(|interface| InterfaceRoute {
+            interface: interface.name.clone(),
+            mark: interface.mark,
+        })
+        .collect();
+    
+    PolicyDecision {
+        policy: policy.name().to_string(),
+        family: context.family,
+        targets: resolution.targets,
+        hash: resolution.hash,
+        sticky: policy.sticky.as_ref().map(|s| s.timeout),
+        pins,
+        sets,
+        routes,
+    }
+}
+
+fn resolve_nested(context: &PolicyContext<'_>, policy: &Policy) -> Resolution {
+    // 被强制的接口可用时直接使用，否则按正常策略处理
+    if let Some(forced) = forced_member(context.overrides, policy.name())
+        .and_then(|member| context.config.find_member(member))
+    {
+        let resolution = resolve_member(context, forced);
+        if !resolution.targets.is_empty() {
+            return resolution;
+        }
     }
     
-    async fn select_primary_interface(&self, interfaces: &[String], policy: &Policy) -> Result<String> {
-        // 选择主接口逻辑占位
-        interfaces.first()
-            .ok_or_else(|| anyhow::anyhow!("No online interfaces"))
-            .map(|s| s.clone())
+    let members: Vec<Resolution> = policy.interfaces.iter()
+        .filter(|name| !is_excluded(context.overrides, policy.name(), name))
+        .filter_map(|name| context.config.find_member(name))
+        .map(|member| resolve_member(context, member))
+        .filter(|resolution| !resolution.targets.is_empty())
+        .collect();
+    
+    match policy.policy_type.as_str() {
+        // 自动选择：延迟最低的成员
+        "url-test" => members.into_iter()
+            .min_by_key(|r| r.latency.unwrap_or(Duration::MAX))
+            .unwrap_or_else(Resolution::empty),
+        // 负载均衡：合并所有在线成员，同一接口的权重累加
+        "load-balance" => {
+            let latency = members.iter().filter_map(|r| r.latency).min();
+            let mut targets: Vec<RouteTarget> = Vec::new();
+            for target in members.into_iter().flat_map(|r| r.targets) {
+                match targets.iter_mut().find(|t| t.interface == target.interface) {
+                    Some(existing) => existing.weight = existing.weight.saturating_add(target.weight),
+                    None => targets.push(target),
+                }
+            }
+            
+            if let Some(factors) = context.adaptive.get(policy.name()) {
+                for target in &mut targets {
+                    let factor = factors.get(&target.interface).copied().unwrap_or(1.0);
+                    target.weight = ((target.weight as f64 * factor).round() as u32).max(1);
+                }
+            }
+            
+            Resolution {
+                targets,
+                latency,
+                hash: policy.hash.unwrap_or_default(),
+            }
+        }
+        // 故障转移：按顺序取第一个可用成员
+        "fallback" => members.into_iter().next().unwrap_or_else(Resolution::empty),
+        _ => Resolution::empty(),
+    }
+}
+
+fn resolve_member(context: &PolicyContext<'_>, member: PolicyMember<'_>) -> Resolution {
+    match member {
+        PolicyMember::Interface(interface) => {
+            match interface_health(context, interface) {
+                Some(state) if interface.weight > 0 => Resolution {
+                    targets: vec![RouteTarget {
+                        interface: interface.name.clone(),
+                        mark: interface.mark,
+                        weight: interface.weight.saturating_mul(WEIGHT_SCALE),
+                    }],
+                    latency: state.latency,
+                    hash: BalanceHash::default(),
+                },
+                _ => Resolution::empty(),
+            }
+        }
+        PolicyMember::Policy(nested) => resolve_nested(context, nested),
     }
+}
+
+// 接口已启用、在线、未在排空且配额未用尽时返回其健康状态
+fn interface_health<'a>(context: &PolicyContext<'a>, interface: &Interface) -> Option<&'a InterfaceHealth> {
+    if drain_of(context.overrides, &interface.name).is_some()
+        || context.quota_exhausted.contains_key(&interface.name)
+    {
+        return None;
+    }
+    context.health.get(&interface.name)
+        .filter(|h| interface.enabled && h.is_online)
+}
+
+// 按健康统计将各自适应策略的权重系数向目标值推进一步，返回系数是否发生变化
+pub fn step_adaptive_factors(
+    config: &Config,
+    health: &HashMap<String, InterfaceHealth>,
+    throughput: &HashMap<String, f64>,
+    factors: &mut HashMap<String, HashMap<String, f64>>,
+) -> bool {
+    factors.retain(|name, _| config.find_policy(name).is_some_and(|p| p.adaptive.is_some()));
     
-    async fn update_routing_rules(&self, interface: &str) -> Result<()> {
-        // 更新路由规则占位
-        self.nftables.update_rules(interface).await?;
-        Ok(())
+    let mut changed = false;
+    for policy in &config.policies {
+        let Some(adaptive) = &policy.adaptive else {
+            continue;
+        };
+        let policy_factors = factors.entry(policy.name().to_string()).or_default();
+        
+        for interface in &config.interfaces {
+            let target = health.get(&interface.name)
+                .map(|h| adaptive_target_factor(
+                    adaptive,
+                    h,
+                    throughput.get(&interface.name).copied(),
+                    interface.bandwidth,
+                ))
+                .unwrap_or(1.0);
+            
+            let current = policy_factors.entry(interface.name.clone()).or_insert(1.0);
+            // 每个周期的变化量受 max-step 限制，平滑迁移流量
+            let next = *current + (target - *current).clamp(-adaptive.max_step, adaptive.max_step);
+            if (next - *current).abs() > f64::EPSILON {
+                tracing::debug!(
+                    "策略 {} 接口 {} 权重系数: {:.2} -> {:.2}",
+                    policy.name(), interface.name, *current, next
+                );
+                *current = next;
+                changed = true;
+            }
+        }
     }
     
-    async fn setup_round_robin_rules(&self, interfaces: &[String], policy: &Policy) -> Result<()> {
-        // 设置轮询规则占位
-        self.nftables.setup_round_robin(interfaces, policy).await?;
-        Ok(())
+    changed
+}
+
+// 计算接口的目标权重系数：延迟超标、丢包和带宽占用都会降低系数
+fn adaptive_target_factor(
+    adaptive: &AdaptiveConfig,
+    health: &InterfaceHealth,
+    throughput: Option<f64>,
+    bandwidth: Option<u32>,
+) -> f64 {
+    let mut factor = 1.0;
+    
+    if let Some(latency) = health.average_latency() {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let target_ms = adaptive.latency_target as f64;
+        if target_ms > 0.0 && latency_ms > target_ms {
+            factor *= target_ms / latency_ms;
+        }
     }
     
-    async fn setup_failover_rules(&self, primary: &str) -> Result<()> {
-        // 设置故障转移规则占位
-        self.nftables.setup_failover(primary).await?;
-        Ok(())
+    factor *= 1.0 - health.loss_rate();
+    
+    if let (Some(bps), Some(bandwidth)) = (throughput, bandwidth.filter(|b| *b > 0)) {
+        let utilization = (bps / (bandwidth as f64 * 1_000_000.0)).min(1.0);
+        factor *= 1.0 - 0.8 * utilization;
     }
     
-    pub async fn handle_interface_change(&self, interface: &str, is_online: bool) -> Result<()> {
-        // 处理接口状态变化占位
-        if let Some(policy_name) = self.current_policy.read().await.as_ref() {
-            self.apply_policy(policy_name).await?;
+    factor.clamp(adaptive.min_factor, 1.0)
+}
+
+fn target_status(targets: &[RouteTarget]) -> Vec<TargetStatus> {
+    targets.iter()
+        .map(|t| TargetStatus {
+            interface: t.interface.clone(),
+            weight: t.display_weight(),
+        })
+        .collect()
+}
+
+impl Resolution {
+    fn empty() -> Self {
+        Self {
+            targets: Vec::new(),
+            latency: None,
+            hash: BalanceHash::default(),
         }
-        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NftablesConfig;
+    
+    fn config() -> Config {
+        serde_yaml::from_str(r#"
+global:
+  policy: "balanced"
+  udp-race: false
+  mptcp: false
+  tfo: false
+  health-check:
+    timeout: 3
+    interval: 10
+    url: "http://example.com"
+    fail-threshold: 3
+    succ-threshold: 2
+interfaces:
+  - {name: "wan1", interface-name: "eth1", weight: 3, mark: 1, enabled: true, nftables-sets: []}
+  - {name: "wan2", interface-name: "eth2", weight: 2, mark: 2, enabled: true, nftables-sets: []}
+  - {name: "wan3", interface-name: "eth3", weight: 1, mark: 3, enabled: true, nftables-sets: []}
+policies:
+  - name: "balanced"
+    type: "load-balance"
+    interfaces: ["wan1", "wan2", "wan3"]
+    sticky: {timeout: 600}
+"#).unwrap()
+    }
+    
+    fn decide(config: &Config, online: &[&str], overrides: &[Override]) -> Vec<PolicyDecision> {
+        let health = online.iter()
+            .map(|name| {
+                let mut health = InterfaceHealth::new();
+                health.is_online = true;
+                (name.to_string(), health)
+            })
+            .collect();
+        let context = PolicyContext {
+            config,
+            family: Family::V4,
+            health: &health,
+            adaptive: &HashMap::new(),
+            overrides,
+            quota_exhausted: &HashMap::new(),
+            set_families: &HashMap::new(),
+        };
+        vec![resolve_policy(&context, config.find_policy("balanced").unwrap())]
+    }
+    
+    // apply_policy 在规则集与已应用的一致时只应用 render_route_updates 生成的元素更新
+    fn assert_element_only(config: &Config, before: &[PolicyDecision], after: &[PolicyDecision]) -> Vec<String> {
+        let nftables = NftablesManager::new(&NftablesConfig::default());
+        let render = |decisions| nftables.render_ruleset(&config.interfaces, decisions, &[], &HashMap::new());
+        assert_eq!(render(before), render(after));
+        let updates = nftables.render_route_updates(before, after);
+        assert!(!updates.is_empty());
+        assert!(updates.iter().all(|line| line.contains(" element ") || line.starts_with("flush map ")));
+        updates
+    }
+    
+    #[test]
+    fn member_going_offline_only_updates_map_elements() {
+        let config = config();
+        let before = decide(&config, &["wan1", "wan2", "wan3"], &[]);
+        let after = decide(&config, &["wan1", "wan3"], &[]);
+        assert_eq!(after[0].targets.len(), 2);
+        
+        let updates = assert_element_only(&config, &before, &after);
+        assert!(updates.contains(&"delete element inet mwan3 mwan3_route_v4 { 0x2 }".to_string()));
+        assert!(updates.iter().any(|line| line.starts_with("add element inet mwan3 mwan3_balance_v4")
+            && !line.contains("wan2")));
+        
+        // 恢复上线同样只更新元素
+        assert_element_only(&config, &after, &before);
+    }
+    
+    #[test]
+    fn drain_and_force_only_update_map_elements() {
+        let config = config();
+        let online = ["wan1", "wan2", "wan3"];
+        let before = decide(&config, &online, &[]);
+        
+        let drain = Override::new(OverrideKind::Drain { interface: "wan1".to_string(), deadline: None }, None);
+        assert_element_only(&config, &before, &decide(&config, &online, &[drain]));
+        
+        let force = Override::new(OverrideKind::Force { policy: "balanced".to_string(), member: "wan3".to_string() }, None);
+        let forced = decide(&config, &online, &[force]);
+        assert_eq!(forced[0].targets.iter().map(|t| t.interface.as_str()).collect::<Vec<_>>(), ["wan3"]);
+        assert_element_only(&config, &before, &forced);
+    }
+    
+    #[test]
+    fn unchanged_decision_needs_no_updates() {
+        let config = config();
+        let nftables = NftablesManager::new(&NftablesConfig::default());
+        let decisions = decide(&config, &["wan1", "wan2"], &[]);
+        assert!(nftables.render_route_updates(&decisions, &decisions).is_empty());
     }
 }
\ No newline at end of file
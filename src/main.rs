@@ -11,6 +11,20 @@ mod interface_monitor;
 mod udp_race;
 mod mptcp;
 mod nftables;
+mod stats;
+mod simulate;
+mod overrides;
+mod status;
+mod conntrack;
+mod schedule;
+mod quota;
+mod connected;
+mod sets;
+mod cidr_sources;
+mod domains;
+mod dns_forwarder;
+mod resolv;
+mod fsutil;
 
 use config::Config;
 use daemon::{DaemonManager, setup_signal_handlers};
@@ -20,6 +34,8 @@ use interface_monitor::InterfaceMonitor;
 use udp_race::UdpRaceManager;
 use mptcp::MptcpManager;
 use nftables::NftablesManager;
+use dns_forwarder::DnsForwarder;
+use sets::SetGenerator;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -48,6 +64,68 @@ async fn main() -> Result<()> {
             .long("stop")
             .help("停止daemon进程")
             .action(clap::ArgAction::SetTrue))
+        .subcommand(Command::new("simulate")
+            .about("按健康事件时间线模拟策略决策和规则变化")
+            .arg(Arg::new("timeline")
+                .short('t')
+                .long("timeline")
+                .value_name("FILE")
+                .help("时间线文件，每行形如: t=10s wan1 down")
+                .required(true))
+            .arg(Arg::new("start")
+                .short('s')
+                .long("start")
+                .value_name("TIME")
+                .help("模拟开始时间(RFC 3339)，用于评估计划，默认为当前时间")))
+        .subcommand(Command::new("override")
+            .about("管理运行时覆盖规则")
+            .subcommand_required(true)
+            .subcommand(Command::new("force")
+                .about("强制策略只使用指定成员(接口或嵌套策略)")
+                .arg(Arg::new("policy").required(true))
+                .arg(Arg::new("member").required(true))
+                .arg(expire_arg()))
+            .subcommand(Command::new("exclude")
+                .about("从策略中排除成员")
+                .arg(Arg::new("member").required(true))
+                .arg(Arg::new("policy")
+                    .long("policy")
+                    .value_name("POLICY")
+                    .help("只对指定策略生效"))
+                .arg(expire_arg()))
+            .subcommand(Command::new("pin")
+                .about("指定源地址段固定走某个接口")
+                .arg(Arg::new("source").required(true).help("源地址段，如 192.168.1.0/24"))
+                .arg(Arg::new("interface").required(true))
+                .arg(expire_arg()))
+            .subcommand(Command::new("drain")
+                .about("排空接口：不再分配新连接，已有连接保持到结束")
+                .arg(Arg::new("interface").required(true))
+                .arg(Arg::new("deadline")
+                    .long("deadline")
+                    .value_name("DURATION")
+                    .help("截止时间，到期后清除剩余连接，如 30m"))
+                .arg(expire_arg()))
+            .subcommand(Command::new("list")
+                .about("列出当前有效的覆盖规则"))
+            .subcommand(Command::new("remove")
+                .about("删除覆盖规则")
+                .arg(Arg::new("id").required(true).help("覆盖规则ID，all 表示全部"))))
+        .subcommand(Command::new("status")
+            .about("显示守护进程的运行状态"))
+        .subcommand(Command::new("gen-sets")
+            .about("从 cidr-sources 生成运营商集合的网段列表文件")
+            .arg(Arg::new("set")
+                .long("set")
+                .value_name("NAME")
+                .help("只生成指定的集合")))
+        .subcommand(Command::new("gen-dns")
+            .about("生成按域名分流的 dnsmasq/smartdns 配置片段")
+            .arg(Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(["dnsmasq", "smartdns"])
+                .help("输出到标准输出的格式，不指定时写入 dns-fragments 配置的文件")))
         .get_matches();
 
     let config_path = matches.get_one::<String>("config").unwrap();
@@ -58,6 +136,39 @@ async fn main() -> Result<()> {
     // 初始化日志
     tracing_subscriber::fmt::init();
 
+    // 模拟模式只解析配置并输出决策，不修改系统状态
+    if let Some(simulate_matches) = matches.subcommand_matches("simulate") {
+        let config = Config::load(config_path).await?;
+        let timeline = simulate_matches.get_one::<String>("timeline").unwrap();
+        let start = match simulate_matches.get_one::<String>("start") {
+            Some(value) => chrono::DateTime::parse_from_rfc3339(value)?,
+            None => chrono::Local::now().fixed_offset(),
+        };
+        return simulate::run(&config, timeline, start).await;
+    }
+
+    // 覆盖规则写入状态目录，由守护进程在下一次同步时生效
+    if let Some(override_matches) = matches.subcommand_matches("override") {
+        let config = Config::load(config_path).await?;
+        return overrides::run_command(&config, override_matches).await;
+    }
+
+    if let Some(gen_matches) = matches.subcommand_matches("gen-sets") {
+        let config = Config::load(config_path).await?;
+        return cidr_sources::run_command(&config, gen_matches).await;
+    }
+
+    if let Some(dns_matches) = matches.subcommand_matches("gen-dns") {
+        let config = Config::load(config_path).await?;
+        return domains::run_command(&config, &config.global.nftables.table, dns_matches).await;
+    }
+
+    if matches.subcommand_matches("status").is_some() {
+        let config = Config::load(config_path).await?;
+        status::StatusReport::load(&config.global.state_dir).await?.print();
+        return Ok(());
+    }
+
     // Daemon管理器
     let daemon_manager = DaemonManager::new(pid_file.clone());
 
@@ -88,12 +199,29 @@ async fn main() -> Result<()> {
     tracing::info!("配置文件已加载: {}", config_path);
 
     // 初始化各个管理器
-    let nftables_manager = Arc::new(NftablesManager::new());
+    let nftables_manager = Arc::new(NftablesManager::new(&config.read().await.global.nftables));
+
+    // 更新 dnsmasq/smartdns 配置片段，内容变化时需要重新加载 DNS 服务器
+    match domains::write_fragments(&*config.read().await, nftables_manager.table_name()).await {
+        Ok(results) => {
+            for (path, _) in results.iter().filter(|(_, changed)| *changed) {
+                tracing::info!("已更新 DNS 配置片段 {}，请重新加载 DNS 服务器", path);
+            }
+        }
+        Err(e) => tracing::warn!("生成 DNS 配置片段失败: {}", e),
+    }
     let health_checker = Arc::new(HealthChecker::new(config.clone()));
-    let load_balancer = Arc::new(LoadBalancer::new(config.clone(), health_checker.clone()));
+    let set_generator = Arc::new(SetGenerator::new(config.clone()));
+    let load_balancer = Arc::new(LoadBalancer::new(
+        config.clone(),
+        health_checker.clone(),
+        nftables_manager.clone(),
+        set_generator.clone(),
+    ));
     let interface_monitor = Arc::new(InterfaceMonitor::new(config.clone(), load_balancer.clone()));
     let udp_race_manager = Arc::new(UdpRaceManager::new(config.clone()));
     let mptcp_manager = Arc::new(MptcpManager::new(config.clone()));
+    let dns_forwarder = DnsForwarder::new(config.clone(), nftables_manager.clone());
 
     // 启动所有服务
     tracing::info!("启动 mwan3-nft 服务...");
@@ -129,12 +257,56 @@ async fn main() -> Result<()> {
         }
     });
 
+    // 监听本表的变化，规则被外部修改时由负载均衡器重新安装
+    let nftables_monitor = nftables_manager.clone();
+    let nftables_handle = tokio::spawn(async move {
+        if let Err(e) = nftables_monitor.monitor().await {
+            tracing::error!("nftables 事件监听错误: {}", e);
+        }
+    });
+
+    // 未开启 cidr-sources.refresh 时任务立即结束
+    let set_generator_handle = tokio::spawn(async move {
+        if let Err(e) = set_generator.start().await {
+            tracing::error!("网段列表生成任务错误: {}", e);
+        }
+    });
+
+    // 未配置 dns-forwarder 时任务立即结束
+    let dns_handle = tokio::spawn(async move {
+        if let Err(e) = dns_forwarder.start().await {
+            tracing::error!("DNS 转发器错误: {}", e);
+        }
+    });
+
     // 保持程序运行
     tokio::signal::ctrl_c().await?;
     tracing::info!("收到停止信号，正在关闭...");
 
+    // 停止各个后台任务
+    let handles = [
+        health_handle,
+        interface_handle,
+        load_balancer_handle,
+        udp_race_handle,
+        mptcp_handle,
+        nftables_handle,
+        set_generator_handle,
+        dns_handle,
+    ];
+    for handle in handles {
+        handle.abort();
+    }
+
     // 清理资源占位
     daemon_manager.remove_pid_file()?;
 
     Ok(())
+}
+
+fn expire_arg() -> Arg {
+    Arg::new("expire")
+        .long("expire")
+        .value_name("DURATION")
+        .help("有效期，如 30m、1h")
 }
\ No newline at end of file
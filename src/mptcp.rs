@@ -42,7 +42,7 @@ impl MptcpManager {
     async fn enable_mptcp(&self) -> Result<()> {
         // 启用 MPTCP 占位
         Command::new("sysctl")
-            .args(&["-w", "net.mptcp.enabled=1"])
+            .args(["-w", "net.mptcp.enabled=1"])
             .output()
             .await?;
         
@@ -52,7 +52,7 @@ impl MptcpManager {
     async fn enable_tfo(&self) -> Result<()> {
         // 启用 TCP Fast Open 占位
         Command::new("sysctl")
-            .args(&["-w", "net.ipv4.tcp_fastopen=3"])
+            .args(["-w", "net.ipv4.tcp_fastopen=3"])
             .output()
             .await?;
         
@@ -63,7 +63,7 @@ impl MptcpManager {
         // 设置 MPTCP 调度器占位
         let param = format!("net.mptcp.scheduler={}", scheduler);
         Command::new("sysctl")
-            .args(&["-w", &param])
+            .args(["-w", &param])
             .output()
             .await?;
         
@@ -81,7 +81,7 @@ impl MptcpManager {
     async fn check_mptcp_status(&self) -> Result<()> {
         // 检查 MPTCP 状态占位
         let output = Command::new("ss")
-            .args(&["-M", "-t", "-n"])
+            .args(["-M", "-t", "-n"])
             .output()
             .await?;
         
@@ -103,6 +103,7 @@ impl MptcpManager {
         Ok(())
     }
     
+    #[allow(dead_code)]
     pub async fn configure_interface_mptcp(&self, interface: &str, enable: bool) -> Result<()> {
         // 为特定接口配置 MPTCP 占位
         if enable {
@@ -116,20 +117,22 @@ impl MptcpManager {
         Ok(())
     }
     
+    #[allow(dead_code)]
     async fn add_mptcp_endpoint(&self, interface: &str) -> Result<()> {
         // 添加 MPTCP 端点占位
         Command::new("ip")
-            .args(&["mptcp", "endpoint", "add", "dev", interface])
+            .args(["mptcp", "endpoint", "add", "dev", interface])
             .output()
             .await?;
         
         Ok(())
     }
     
+    #[allow(dead_code)]
     async fn remove_mptcp_endpoint(&self, interface: &str) -> Result<()> {
         // 移除 MPTCP 端点占位
         Command::new("ip")
-            .args(&["mptcp", "endpoint", "delete", "dev", interface])
+            .args(["mptcp", "endpoint", "delete", "dev", interface])
             .output()
             .await?;
         
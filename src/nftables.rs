@@ -1,155 +1,782 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::time::Duration;
+use ipnet::IpNet;
 use tokio::process::Command;
+use tokio::sync::Notify;
 use anyhow::Result;
 
-use crate::config::{Policy, Interface};
+use crate::config::{BalanceHash, Family, Interface, NftablesConfig};
+use crate::connected;
+use crate::load_balancer::{InterfaceRoute, PolicyDecision, RouteTarget, SetRoute, SourcePin};
+
+// 固定的哈希种子，保证规则重建后同一连接仍映射到同一接口
+const HASH_SEED: u32 = 0x6d77_616e;
+// 负载均衡的取值区间数，按权重划分给各接口，与接口数量和权重无关，权重变化只需替换 map 元素
+const BALANCE_SLOTS: u32 = 1000;
+
+const CONNECTED_SET_V4: &str = "mwan3_connected_v4";
+const CONNECTED_SET_V6: &str = "mwan3_connected_v6";
+
+// 入站接口 -> 记录连接标记的链 / 计数器，出站标记 -> 计数器，连接标记 -> 接口链
+const INGRESS_CHAINS: &str = "mwan3_ingress_chains";
+const INGRESS_COUNTERS: &str = "mwan3_ingress_counters";
+const STATS_COUNTERS: &str = "mwan3_stats_counters";
+const RESTORE_MAP: &str = "mwan3_restore";
+
+// 一次事务会产生大量事件，合并短时间内的事件后再检查
+const MONITOR_DEBOUNCE: Duration = Duration::from_millis(200);
 
 pub struct NftablesManager {
     table_name: String,
+    config: NftablesConfig,
+    // 本表发生变化，需要检查规则是否被外部修改
+    table_changed: Notify,
+}
+
+// 表中的链及其规则数、集合(包括 map)和命名计数器，用于检测规则是否被外部修改
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TableState {
+    pub chains: BTreeMap<String, usize>,
+    pub sets: BTreeSet<String>,
+    pub counters: BTreeSet<String>,
 }
 
 impl NftablesManager {
-    pub fn new() -> Self {
+    pub fn new(config: &NftablesConfig) -> Self {
         Self {
-            table_name: "mwan3".to_string(),
+            table_name: config.table.clone(),
+            config: config.clone(),
+            table_changed: Notify::new(),
         }
     }
     
-    pub async fn initialize(&self) -> Result<()> {
-        // 初始化 nftables 表和链占位
-        self.create_table().await?;
-        self.create_chains().await?;
-        Ok(())
+    pub fn table_name(&self) -> &str {
+        &self.table_name
     }
     
-    async fn create_table(&self) -> Result<()> {
-        // 创建 nftables 表占位
-        let cmd = format!("add table inet {}", self.table_name);
-        self.execute_nft_command(&cmd).await?;
-        Ok(())
+    #[allow(dead_code)]
+    pub async fn initialize(&self) -> Result<()> {
+        // 初始化 nftables 表和链
+        let script = self.render_base();
+        self.apply_script(&script).await
     }
     
-    async fn create_chains(&self) -> Result<()> {
-        // 创建基础链占位
-        let chains = vec![
+    pub fn render_base(&self) -> Vec<String> {
+        let table = &self.table_name;
+        let mut script = vec![
+            format!("add table inet {}", table),
+            format!(
+                "add chain inet {} mwan3_prerouting {{ type filter hook prerouting priority {}; }}",
+                table, self.config.prerouting_priority
+            ),
+            format!(
+                "add chain inet {} mwan3_output {{ type route hook output priority {}; }}",
+                table, self.config.output_priority
+            ),
+            format!("add map inet {} {} {{ type mark : verdict; }}", table, RESTORE_MAP),
+        ];
+        
+        // 创建基础链
+        let chains = [
             "mwan3_hook",
+            "mwan3_ingress",
             "mwan3_connected",
             "mwan3_track",
+            "mwan3_dns",
+            "mwan3_overrides",
             "mwan3_policy",
             "mwan3_rules",
+            "mwan3_stats",
         ];
-        
         for chain in chains {
-            let cmd = format!("add chain inet {} {}", self.table_name, chain);
-            self.execute_nft_command(&cmd).await?;
+            script.push(format!("add chain inet {} {}", table, chain));
         }
         
-        // 创建 hook 链
-        let hook_cmd = format!(
-            "add chain inet {} mwan3_hook {{ type route hook output priority mangle; }}",
-            self.table_name
-        );
-        self.execute_nft_command(&hook_cmd).await?;
+        for chain in ["mwan3_prerouting", "mwan3_output", "mwan3_hook", "mwan3_policy"] {
+            script.push(format!("flush chain inet {} {}", table, chain));
+        }
+        script.push(format!("add rule inet {} mwan3_prerouting jump mwan3_ingress", table));
+        script.push(format!("add rule inet {} mwan3_prerouting jump mwan3_hook", table));
+        script.push(format!("add rule inet {} mwan3_output jump mwan3_hook", table));
         
-        Ok(())
+        // 已有连接经接口链恢复连接上保存的标记，新连接依次经过直连、DNS、绑定、集合规则和策略；
+        // 接口链同时写入报文和连接的标记，只修改掩码内的位
+        let unmarked = self.mark_match("meta mark", "==", 0);
+        script.push(format!(
+            "add rule inet {} mwan3_hook {} {} {} vmap @{}",
+            table, unmarked, self.mark_match("ct mark", "!=", 0), self.mark_key("ct mark"), RESTORE_MAP
+        ));
+        for chain in ["mwan3_connected", "mwan3_track", "mwan3_dns", "mwan3_overrides", "mwan3_rules", "mwan3_policy"] {
+            script.push(format!("add rule inet {} mwan3_hook {} jump {}", table, unmarked, chain));
+        }
+        script.push(format!(
+            "add rule inet {} mwan3_hook {} jump mwan3_stats",
+            table, self.mark_match("meta mark", "!=", 0)
+        ));
+        
+        for family in Family::ALL {
+            let chain = Self::policy_chain(family);
+            script.push(format!("add chain inet {} {}", table, chain));
+            script.push(format!(
+                "add rule inet {} mwan3_policy meta nfproto {} goto {}",
+                table, family.nfproto(), chain
+            ));
+            // 标记 -> 接口链，只包含当前可用的接口，接口上下线只需增删元素
+            script.push(format!(
+                "add map inet {} {} {{ type mark : verdict; }}",
+                table, Self::route_map(family)
+            ));
+            // 取值区间 -> 策略当前的出口，标记 -> 会话保持链，同样只包含当前的出口
+            script.push(format!(
+                "add map inet {} {} {{ type mark : verdict; flags interval; }}",
+                table, Self::balance_map(family)
+            ));
+            script.push(format!(
+                "add map inet {} {} {{ type mark : verdict; }}",
+                table, Self::sticky_map(family)
+            ));
+        }
+        
+        script
     }
     
-    pub async fn update_rules(&self, interface: &str) -> Result<()> {
-        // 更新路由规则占位
-        self.clear_interface_rules(interface).await?;
-        self.add_interface_rules(interface).await?;
-        Ok(())
+    // 生成完整的期望规则集：基础链、直连网段、接口标记链和各地址族的当前策略；
+    // 可用接口和策略出口的 vmap 元素由 render_routes 单独生成，不随接口状态变化
+    pub fn render_ruleset(
+        &self,
+        interfaces: &[Interface],
+        decisions: &[PolicyDecision],
+        connected: &[IpNet],
+        dns_servers: &HashMap<String, Vec<IpAddr>>,
+    ) -> Vec<String> {
+        let mut script = self.render_base();
+        script.extend(self.render_connected(connected));
+        script.extend(self.render_interface_chains(interfaces));
+        script.extend(self.render_dns(interfaces, dns_servers));
+        let pins: Vec<SourcePin> = decisions.iter()
+            .flat_map(|d| d.pins.iter().cloned())
+            .collect();
+        script.extend(self.render_pins(&pins));
+        script.extend(self.render_sets(interfaces));
+        script.extend(self.render_set_rules(decisions));
+        for decision in decisions {
+            script.extend(self.render_policy(interfaces, decision));
+        }
+        script
     }
     
-    async fn clear_interface_rules(&self, interface: &str) -> Result<()> {
-        // 清除接口相关规则占位
-        let cmd = format!(
-            "flush chain inet {} mwan3_policy",
-            self.table_name
-        );
-        self.execute_nft_command(&cmd).await?;
-        Ok(())
+    // 发往本机、广播、组播以及直连网段的流量不经过策略分流
+    pub fn render_connected(&self, prefixes: &[IpNet]) -> Vec<String> {
+        let table = &self.table_name;
+        let mut script = vec![
+            format!("add set inet {} {} {{ type ipv4_addr; flags interval; }}", table, CONNECTED_SET_V4),
+            format!("add set inet {} {} {{ type ipv6_addr; flags interval; }}", table, CONNECTED_SET_V6),
+            format!("flush set inet {} {}", table, CONNECTED_SET_V4),
+            format!("flush set inet {} {}", table, CONNECTED_SET_V6),
+        ];
+        
+        let v4: Vec<String> = prefixes.iter()
+            .filter(|p| matches!(p, IpNet::V4(_)))
+            .map(|p| p.to_string())
+            .collect();
+        let v6: Vec<String> = prefixes.iter()
+            .filter(|p| matches!(p, IpNet::V6(_)))
+            .map(|p| p.to_string())
+            .collect();
+        for (set, elements) in [(CONNECTED_SET_V4, v4), (CONNECTED_SET_V6, v6)] {
+            if !elements.is_empty() {
+                script.push(format!(
+                    "add element inet {} {} {{ {} }}",
+                    table, set, elements.join(", ")
+                ));
+            }
+        }
+        
+        script.push(format!("flush chain inet {} mwan3_connected", table));
+        script.push(format!(
+            "add rule inet {} mwan3_connected fib daddr type {{ local, broadcast, multicast }} accept",
+            table
+        ));
+        script.push(format!("add rule inet {} mwan3_connected ip daddr @{} accept", table, CONNECTED_SET_V4));
+        script.push(format!("add rule inet {} mwan3_connected ip6 daddr @{} accept", table, CONNECTED_SET_V6));
+        script
     }
     
-    async fn add_interface_rules(&self, interface: &str) -> Result<()> {
-        // 添加接口规则占位
-        let cmd = format!(
-            "add rule inet {} mwan3_policy oif {} mark set 0x{:x}",
-            self.table_name, interface, 1
-        );
-        self.execute_nft_command(&cmd).await?;
-        Ok(())
+    // 运营商的 DNS 服务器通常只应答本线路的查询，发往这些服务器的 DNS 流量固定走对应接口；
+    // 经路由 map 分发，接口不可用(下线、排空、配额用尽)时回落到正常策略
+    pub fn render_dns(&self, interfaces: &[Interface], servers: &HashMap<String, Vec<IpAddr>>) -> Vec<String> {
+        let mut script = vec![format!("flush chain inet {} mwan3_dns", self.table_name)];
+        for interface in interfaces {
+            let Some(addrs) = servers.get(&interface.name) else {
+                continue;
+            };
+            for family in Family::ALL {
+                let addrs: Vec<String> = addrs.iter()
+                    .filter(|addr| match family {
+                        Family::V4 => addr.is_ipv4(),
+                        Family::V6 => addr.is_ipv6(),
+                    })
+                    .map(|addr| addr.to_string())
+                    .collect();
+                if addrs.is_empty() {
+                    continue;
+                }
+                script.push(format!(
+                    "add rule inet {} mwan3_dns {} daddr {{ {} }} meta l4proto {{ tcp, udp }} th dport 53 {}",
+                    self.table_name,
+                    family.keyword(),
+                    addrs.join(", "),
+                    self.route_dispatch(family, interface.mark)
+                ));
+            }
+        }
+        script.push(self.render_route_fallthrough("mwan3_dns"));
+        script
+    }
+    
+    pub fn render_pins(&self, pins: &[SourcePin]) -> Vec<String> {
+        // 按源地址固定出口，优先于集合规则和策略
+        let mut script = vec![format!("flush chain inet {} mwan3_overrides", self.table_name)];
+        for pin in pins {
+            let family = match pin.source {
+                IpNet::V4(_) => Family::V4,
+                IpNet::V6(_) => Family::V6,
+            };
+            script.push(format!(
+                "add rule inet {} mwan3_overrides {} saddr {} {}",
+                self.table_name, family.keyword(), pin.source, self.route_dispatch(family, pin.mark)
+            ));
+        }
+        script.push(self.render_route_fallthrough("mwan3_overrides"));
+        script
+    }
+    
+    // 创建由 mwan3-nft 维护的集合，已存在时保留其中的元素
+    pub fn render_sets(&self, interfaces: &[Interface]) -> Vec<String> {
+        interfaces.iter()
+            .flat_map(|i| &i.nftables_sets)
+            .filter(|set| set.managed)
+            .filter_map(|set| {
+                let family = set.family?;
+                // 带超时的动态集合由 DNS 服务器逐个写入地址，其余集合按网段写入
+                let flags = match set.element_timeout() {
+                    Some(timeout) => format!("flags timeout; timeout {}s;", timeout),
+                    None => "flags interval;".to_string(),
+                };
+                Some(format!(
+                    "add set inet {} {} {{ type {}_addr; {} }}",
+                    self.table_name, set.name, family.nfproto(), flags
+                ))
+            })
+            .collect()
+    }
+    
+    // 匹配接口绑定集合的流量直接走该接口，接口不可用时继续匹配后续规则
+    pub fn render_set_rules(&self, decisions: &[PolicyDecision]) -> Vec<String> {
+        let mut script = vec![format!("flush chain inet {} mwan3_rules", self.table_name)];
+        for decision in decisions {
+            for SetRoute { set, direction, mark, .. } in &decision.sets {
+                script.push(format!(
+                    "add rule inet {} mwan3_rules {} {} @{} {}",
+                    self.table_name,
+                    decision.family.keyword(),
+                    direction.keyword(),
+                    set,
+                    self.route_dispatch(decision.family, *mark)
+                ));
+            }
+        }
+        script.push(self.render_route_fallthrough("mwan3_rules"));
+        script
+    }
+    
+    // 先写入目标接口的标记，再按标记查找可用接口的 vmap，接口不可用时查找失败，继续下一条规则
+    fn route_dispatch(&self, family: Family, mark: u32) -> String {
+        self.map_dispatch(&Self::route_map(family), mark)
+    }
+    
+    fn map_dispatch(&self, map: &str, mark: u32) -> String {
+        format!(
+            "{} {} vmap @{}",
+            self.mark_set("meta mark", self.config.mark_value(mark)),
+            self.mark_key("meta mark"),
+            map
+        )
     }
     
-    pub async fn setup_round_robin(&self, interfaces: &[String], policy: &Policy) -> Result<()> {
-        // 设置轮询规则占位
-        self.clear_policy_rules().await?;
+    // 没有命中可用接口时清除临时写入的标记，交给后续的链处理
+    fn render_route_fallthrough(&self, chain: &str) -> String {
+        format!("add rule inet {} {} {}", self.table_name, chain, self.mark_set("meta mark", 0))
+    }
+    
+    // 按掩码比较标记，掩码覆盖全部 32 位时直接比较
+    fn mark_match(&self, key: &str, op: &str, value: u32) -> String {
+        match (self.config.mark_mask, op) {
+            (u32::MAX, "==") => format!("{} 0x{:x}", key, value),
+            (u32::MAX, _) => format!("{} {} 0x{:x}", key, op, value),
+            (mask, _) => format!("{} and 0x{:x} {} 0x{:x}", key, mask, op, value),
+        }
+    }
+    
+    // 用作 map 键的标记，只取掩码内的位
+    fn mark_key(&self, key: &str) -> String {
+        match self.config.mark_mask {
+            u32::MAX => key.to_string(),
+            mask => format!("{} and 0x{:x}", key, mask),
+        }
+    }
+    
+    // 只改写掩码内的位，保留其他程序使用的标记位
+    fn mark_set(&self, key: &str, value: u32) -> String {
+        match (self.config.mark_mask, value) {
+            (u32::MAX, _) => format!("{} set 0x{:x}", key, value),
+            (mask, 0) => format!("{} set {} and 0x{:x}", key, key, !mask),
+            (mask, _) => format!("{} set {} and 0x{:x} or 0x{:x}", key, key, !mask, value),
+        }
+    }
+    
+    // 各地址族可用接口和策略出口的 vmap 元素，整体替换
+    pub fn render_routes(&self, decisions: &[PolicyDecision]) -> Vec<String> {
+        let mut script = Vec::new();
+        for decision in decisions {
+            let map = Self::route_map(decision.family);
+            script.push(format!("flush map inet {} {}", self.table_name, map));
+            if !decision.routes.is_empty() {
+                script.push(format!(
+                    "add element inet {} {} {{ {} }}",
+                    self.table_name, map, self.route_elements(decision.routes.iter())
+                ));
+            }
+            script.extend(self.render_target_elements(decision));
+        }
+        script
+    }
+    
+    // 与上一次的决策相比，只增删变化的可用接口，策略出口变化时整体替换其 map 元素
+    pub fn render_route_updates(&self, previous: &[PolicyDecision], decisions: &[PolicyDecision]) -> Vec<String> {
+        let mut script = Vec::new();
+        for decision in decisions {
+            let old_targets = previous.iter()
+                .find(|p| p.family == decision.family)
+                .map(|p| self.render_target_elements(p));
+            let targets = self.render_target_elements(decision);
+            if old_targets.as_ref() != Some(&targets) {
+                script.extend(targets);
+            }
+            
+            let map = Self::route_map(decision.family);
+            let old: Vec<&InterfaceRoute> = previous.iter()
+                .filter(|p| p.family == decision.family)
+                .flat_map(|p| &p.routes)
+                .collect();
+            let removed: Vec<&InterfaceRoute> = old.iter()
+                .copied()
+                .filter(|route| !decision.routes.contains(route))
+                .collect();
+            let added: Vec<&InterfaceRoute> = decision.routes.iter()
+                .filter(|route| !old.contains(route))
+                .collect();
+            if !removed.is_empty() {
+                let marks: Vec<String> = removed.iter()
+                    .map(|r| format!("0x{:x}", self.config.mark_value(r.mark)))
+                    .collect();
+                script.push(format!(
+                    "delete element inet {} {} {{ {} }}",
+                    self.table_name, map, marks.join(", ")
+                ));
+            }
+            if !added.is_empty() {
+                script.push(format!(
+                    "add element inet {} {} {{ {} }}",
+                    self.table_name, map, self.route_elements(added.into_iter())
+                ));
+            }
+        }
+        script
+    }
+    
+    // 策略出口的 map 元素：按权重划分的取值区间，以及启用会话保持时各出口的会话保持链
+    fn render_target_elements(&self, decision: &PolicyDecision) -> Vec<String> {
+        let family = decision.family;
+        let targets: Vec<&RouteTarget> = decision.targets.iter().filter(|t| t.weight > 0).collect();
+        let entry_chain = |target: &RouteTarget| match decision.sticky {
+            Some(_) => Self::sticky_chain(family, &target.interface),
+            None => Self::interface_chain(&target.interface),
+        };
         
-        for (i, interface) in interfaces.iter().enumerate() {
-            let weight = i + 1;
-            let cmd = format!(
-                "add rule inet {} mwan3_policy numgen random mod {} vmap {{ {} : mark set 0x{:x} }}",
-                self.table_name, interfaces.len(), i, weight
-            );
-            self.execute_nft_command(&cmd).await?;
+        let mut script = Vec::new();
+        let balance_map = Self::balance_map(family);
+        script.push(format!("flush map inet {} {}", self.table_name, balance_map));
+        let elements: Vec<String> = balance_slots(&targets).into_iter()
+            .map(|(target, start, end)| match start == end {
+                true => format!("0x{:x} : goto {}", start, entry_chain(target)),
+                false => format!("0x{:x}-0x{:x} : goto {}", start, end, entry_chain(target)),
+            })
+            .collect();
+        if !elements.is_empty() {
+            script.push(format!(
+                "add element inet {} {} {{ {} }}",
+                self.table_name, balance_map, elements.join(", ")
+            ));
         }
         
-        Ok(())
+        let sticky_map = Self::sticky_map(family);
+        script.push(format!("flush map inet {} {}", self.table_name, sticky_map));
+        if decision.sticky.is_some() && !targets.is_empty() {
+            let elements: Vec<String> = targets.iter()
+                .map(|t| format!("0x{:x} : goto {}", self.config.mark_value(t.mark), entry_chain(t)))
+                .collect();
+            script.push(format!(
+                "add element inet {} {} {{ {} }}",
+                self.table_name, sticky_map, elements.join(", ")
+            ));
+        }
+        script
     }
     
-    pub async fn setup_failover(&self, primary: &str) -> Result<()> {
-        // 设置故障转移规则占位
-        self.clear_policy_rules().await?;
+    fn route_elements<'a>(&self, routes: impl Iterator<Item = &'a InterfaceRoute>) -> String {
+        let elements: Vec<String> = routes
+            .map(|r| format!(
+                "0x{:x} : goto {}",
+                self.config.mark_value(r.mark),
+                Self::interface_chain(&r.interface)
+            ))
+            .collect();
+        elements.join(", ")
+    }
+    
+    pub async fn apply_ruleset(&self, script: &[String]) -> Result<()> {
+        self.apply_script(script).await
+    }
+    
+    pub fn render_interface_chains(&self, interfaces: &[Interface]) -> Vec<String> {
+        let table = &self.table_name;
+        let mut script = vec![
+            format!("add map inet {} {} {{ type ifname : verdict; }}", table, INGRESS_CHAINS),
+            format!("add map inet {} {} {{ type ifname : counter; }}", table, INGRESS_COUNTERS),
+            format!("add map inet {} {} {{ type mark : counter; }}", table, STATS_COUNTERS),
+            format!("flush chain inet {} mwan3_stats", table),
+            format!("flush chain inet {} mwan3_ingress", table),
+        ];
+        for map in [INGRESS_CHAINS, INGRESS_COUNTERS, STATS_COUNTERS, RESTORE_MAP] {
+            script.push(format!("flush map inet {} {}", table, map));
+        }
         
-        let cmd = format!(
-            "add rule inet {} mwan3_policy mark set 0x1",
-            self.table_name
-        );
-        self.execute_nft_command(&cmd).await?;
+        // 链和计数器需要在引用它们的 map 元素之前创建
+        let mut ingress_chains = Vec::new();
+        let mut ingress_counters = Vec::new();
+        let mut stats_counters = Vec::new();
+        let mut restore = Vec::new();
+        for interface in interfaces {
+            let mark = self.config.mark_value(interface.mark);
+            let counter = Self::interface_counter(&interface.name);
+            script.push(format!("add counter inet {} {}", table, counter));
+            
+            let chain = Self::interface_chain(&interface.name);
+            let conn_chain = Self::conn_chain(&interface.name);
+            for chain in [&chain, &conn_chain] {
+                script.push(format!("add chain inet {} {}", table, chain));
+                script.push(format!("flush chain inet {} {}", table, chain));
+            }
+            script.push(format!("add rule inet {} {} {}", table, chain, self.mark_set("meta mark", mark)));
+            script.push(format!("add rule inet {} {} {}", table, chain, self.mark_set("ct mark", mark)));
+            script.push(format!("add rule inet {} {} {}", table, conn_chain, self.mark_set("ct mark", mark)));
+            
+            ingress_chains.push(format!("\"{}\" : jump {}", interface.interface_name, conn_chain));
+            ingress_counters.push(format!("\"{}\" : \"{}\"", interface.interface_name, counter));
+            stats_counters.push(format!("0x{:x} : \"{}\"", mark, counter));
+            restore.push(format!("0x{:x} : jump {}", mark, chain));
+            
+            for family in Family::ALL {
+                script.push(format!(
+                    "add set inet {} {} {{ type {}_addr; flags dynamic, timeout; }}",
+                    table, Self::sticky_set(family, &interface.name), family.nfproto()
+                ));
+            }
+        }
+        let maps = [
+            (INGRESS_CHAINS, ingress_chains),
+            (INGRESS_COUNTERS, ingress_counters),
+            (STATS_COUNTERS, stats_counters),
+            (RESTORE_MAP, restore),
+        ];
+        for (map, elements) in maps {
+            if !elements.is_empty() {
+                script.push(format!("add element inet {} {} {{ {} }}", table, map, elements.join(", ")));
+            }
+        }
         
-        Ok(())
+        // 从 WAN 进入的新连接记录该接口的标记，应答报文恢复标记后从同一接口返回；
+        // 入站报文本身不参与分流，只计入该接口的流量统计
+        script.push(format!(
+            "add rule inet {} mwan3_ingress ct state new iifname vmap @{}",
+            table, INGRESS_CHAINS
+        ));
+        script.push(format!(
+            "add rule inet {} mwan3_ingress counter name iifname map @{} accept",
+            table, INGRESS_COUNTERS
+        ));
+        // 按标记统计经过各接口的出站流量，包括沿用连接标记的后续报文
+        script.push(format!(
+            "add rule inet {} mwan3_stats counter name {} map @{}",
+            table, self.mark_key("meta mark"), STATS_COUNTERS
+        ));
+        script
     }
     
-    async fn clear_policy_rules(&self) -> Result<()> {
-        // 清除策略规则占位
-        let cmd = format!("flush chain inet {} mwan3_policy", self.table_name);
-        self.execute_nft_command(&cmd).await?;
-        Ok(())
+    // 按地址族分派到各自的策略链，两个地址族可以使用不同的策略
+    pub fn render_policy(&self, interfaces: &[Interface], decision: &PolicyDecision) -> Vec<String> {
+        let family = decision.family;
+        let chain = Self::policy_chain(family);
+        let mut script = vec![
+            format!("add chain inet {} {}", self.table_name, chain),
+            format!("flush chain inet {} {}", self.table_name, chain),
+        ];
+        if !decision.policy.is_empty() {
+            // 统计由当前策略分配出口的新连接
+            let counter = Self::policy_counter(&decision.policy);
+            script.push(format!("add counter inet {} {}", self.table_name, counter));
+            script.push(format!("add rule inet {} {} counter name {}", self.table_name, chain, counter));
+        }
+        script.extend(self.render_sticky(interfaces, family, decision.sticky));
+        
+        // 出口及其权重只体现在 map 元素中，接口上下线和权重变化不改动规则
+        let balance_map = Self::balance_map(family);
+        for selector in Self::balance_selectors(decision.hash, family) {
+            let rule = match selector {
+                None => format!("numgen random mod {} vmap @{}", BALANCE_SLOTS, balance_map),
+                Some(selector) => format!("{} mod {} seed 0x{:x} vmap @{}", selector, BALANCE_SLOTS, HASH_SEED, balance_map),
+            };
+            script.push(format!("add rule inet {} {} {}", self.table_name, chain, rule));
+        }
+        
+        script
     }
     
-    pub async fn setup_interface_sets(&self, interface: &Interface) -> Result<()> {
-        // 设置接口相关的 sets 规则占位
-        for set_name in &interface.nftables_sets {
-            let cmd = format!(
-                "add rule inet {} mwan3_rules ip saddr @{} mark set 0x{:x}",
-                self.table_name, set_name, interface.mark
-            );
-            self.execute_nft_command(&cmd).await?;
+    // 会话保持：每个接口的动态集合记录分配到该接口的源地址，命中且接口仍可用时直接沿用；
+    // 按接口分开记录，避免从 map 读出标记时覆盖掩码外的标记位
+    fn render_sticky(&self, interfaces: &[Interface], family: Family, timeout: Option<u64>) -> Vec<String> {
+        let policy_chain = Self::policy_chain(family);
+        let Some(timeout) = timeout else {
+            // 未启用会话保持时清空历史记录
+            return interfaces.iter()
+                .map(|i| format!("flush set inet {} {}", self.table_name, Self::sticky_set(family, &i.name)))
+                .collect();
+        };
+        
+        let mut script = Vec::new();
+        for interface in interfaces {
+            let set = Self::sticky_set(family, &interface.name);
+            let chain = Self::sticky_chain(family, &interface.name);
+            script.push(format!("add chain inet {} {}", self.table_name, chain));
+            script.push(format!("flush chain inet {} {}", self.table_name, chain));
+            script.push(format!(
+                "add rule inet {} {} update @{} {{ {} saddr timeout {}s }}",
+                self.table_name, chain, set, family.keyword(), timeout
+            ));
+            script.push(format!(
+                "add rule inet {} {} goto {}",
+                self.table_name, chain, Self::interface_chain(&interface.name)
+            ));
         }
         
-        Ok(())
+        // 经会话保持 map 分派，记录指向的接口不再是策略出口时继续走正常分流
+        let sticky_map = Self::sticky_map(family);
+        for interface in interfaces {
+            script.push(format!(
+                "add rule inet {} {} {} saddr @{} {}",
+                self.table_name,
+                policy_chain,
+                family.keyword(),
+                Self::sticky_set(family, &interface.name),
+                self.map_dispatch(&sticky_map, interface.mark)
+            ));
+        }
+        script.push(self.render_route_fallthrough(&policy_chain));
+        
+        script
     }
     
-    pub async fn update_interface_mark(&self, interface: &str, mark: u32, enabled: bool) -> Result<()> {
-        // 更新接口标记占位
-        if enabled {
-            let cmd = format!(
-                "add rule inet {} mwan3_track oif {} mark set 0x{:x}",
-                self.table_name, interface, mark
-            );
-            self.execute_nft_command(&cmd).await?;
-        } else {
-            // 移除接口标记规则
-            self.remove_interface_mark_rules(interface).await?;
+    pub async fn clear_sticky(&self, family: Family, interface: &str) -> Result<()> {
+        // 清空指向该接口的会话保持记录
+        let set = Self::sticky_set(family, interface);
+        self.execute_nft_command(&format!("flush set inet {} {}", self.table_name, set)).await
+    }
+    
+    // 读取表中所有命名计数器: 名称 -> (报文数, 字节数)
+    pub async fn read_counters(&self) -> Result<HashMap<String, (u64, u64)>> {
+        let output = Command::new("nft")
+            .args(["-j", "list", "counters", "table", "inet", &self.table_name])
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("nft list counters failed: {}", stderr));
         }
         
-        Ok(())
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        let counters = json["nftables"].as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                let counter = &item["counter"];
+                Some((
+                    counter["name"].as_str()?.to_string(),
+                    (counter["packets"].as_u64()?, counter["bytes"].as_u64()?),
+                ))
+            })
+            .collect();
+        
+        Ok(counters)
     }
     
-    async fn remove_interface_mark_rules(&self, interface: &str) -> Result<()> {
-        // 移除接口标记规则占位
-        // 这里需要更复杂的规则删除逻辑
-        Ok(())
+    pub fn interface_counter(interface: &str) -> String {
+        format!("mwan3_traffic_{}", interface)
+    }
+    
+    pub fn policy_counter(policy: &str) -> String {
+        format!("mwan3_policy_{}", policy)
+    }
+    
+    pub fn policy_chain(family: Family) -> String {
+        format!("mwan3_policy_{}", family.suffix())
+    }
+    
+    fn sticky_set(family: Family, interface: &str) -> String {
+        format!("mwan3_sticky_{}_{}_src", family.suffix(), interface)
+    }
+    
+    pub fn sticky_chain(family: Family, interface: &str) -> String {
+        format!("mwan3_sticky_{}_{}", family.suffix(), interface)
+    }
+    
+    // 分流表达式，None 表示随机分流
+    fn balance_selectors(hash: BalanceHash, family: Family) -> Vec<Option<String>> {
+        let ip = family.keyword();
+        match hash {
+            BalanceHash::Random => vec![None],
+            BalanceHash::Src => vec![Some(format!("jhash {ip} saddr"))],
+            BalanceHash::SrcDst => vec![Some(format!("jhash {ip} saddr . {ip} daddr"))],
+            // 端口只对 TCP/UDP 有意义，其余协议退化为按源和目的地址哈希
+            BalanceHash::FiveTuple => vec![
+                Some(format!("meta l4proto {{ tcp, udp }} jhash {ip} saddr . {ip} daddr . meta l4proto . th sport . th dport")),
+                Some(format!("jhash {ip} saddr . {ip} daddr")),
+            ],
+        }
+    }
+    
+    pub fn interface_chain(interface: &str) -> String {
+        format!("mwan3_iface_{}", interface)
+    }
+    
+    // 只写入连接标记的链，用于入站连接
+    fn conn_chain(interface: &str) -> String {
+        format!("mwan3_conn_{}", interface)
+    }
+    
+    fn route_map(family: Family) -> String {
+        format!("mwan3_route_{}", family.suffix())
+    }
+    
+    fn balance_map(family: Family) -> String {
+        format!("mwan3_balance_{}", family.suffix())
+    }
+    
+    fn sticky_map(family: Family) -> String {
+        format!("mwan3_sticky_{}", family.suffix())
+    }
+    
+    // 根据集合的元素类型判断地址族
+    pub async fn set_family(&self, set_name: &str) -> Result<Family> {
+        let output = Command::new("nft")
+            .args(["-j", "list", "set", "inet", &self.table_name, set_name])
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("nft list set {} failed: {}", set_name, stderr));
+        }
+        
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        let set_type = json["nftables"].as_array()
+            .into_iter()
+            .flatten()
+            .find_map(|item| item["set"]["type"].as_str());
+        match set_type {
+            Some("ipv4_addr") => Ok(Family::V4),
+            Some("ipv6_addr") => Ok(Family::V6),
+            other => Err(anyhow::anyhow!("Set {} has unsupported type: {:?}", set_name, other)),
+        }
+    }
+    
+    // 读取集合中的元素，包含无法表示为网段的元素(如地址范围)时返回 None
+    pub async fn set_elements(&self, set_name: &str) -> Result<Option<Vec<IpNet>>> {
+        let output = Command::new("nft")
+            .args(["-j", "list", "set", "inet", &self.table_name, set_name])
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("nft list set {} failed: {}", set_name, stderr));
+        }
+        
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        let elements = json["nftables"].as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| item["set"]["elem"].as_array())
+            .flatten()
+            .map(Self::parse_element)
+            .collect();
+        Ok(elements)
+    }
+    
+    // nft -j 输出中的元素可以是地址字符串、{"prefix": ...}，带超时等属性时包装在 {"elem": {"val": ...}} 中
+    fn parse_element(element: &serde_json::Value) -> Option<IpNet> {
+        if let Some(value) = element.as_str() {
+            return connected::parse_prefix(value);
+        }
+        if let Some(prefix) = element.get("prefix") {
+            let addr = prefix["addr"].as_str()?;
+            let len = prefix["len"].as_u64()?;
+            return format!("{}/{}", addr, len).parse().ok();
+        }
+        element.get("elem").and_then(|elem| Self::parse_element(&elem["val"]))
+    }
+    
+    // 按差异增删集合元素，不重建集合，已匹配的连接和其余元素不受影响
+    pub async fn update_set_elements(&self, set_name: &str, add: &[IpNet], delete: &[IpNet]) -> Result<()> {
+        let mut script = self.element_commands("delete", set_name, delete);
+        script.extend(self.element_commands("add", set_name, add));
+        self.apply_script(&script).await
+    }
+    
+    // 在同一事务中清空集合并重新写入全部元素
+    pub async fn replace_set_elements(&self, set_name: &str, elements: &[IpNet]) -> Result<()> {
+        let mut script = vec![format!("flush set inet {} {}", self.table_name, set_name)];
+        script.extend(self.element_commands("add", set_name, elements));
+        self.apply_script(&script).await
+    }
+    
+    // 向动态集合写入带超时的地址，地址已存在时按新的超时重新计时
+    pub async fn add_timed_elements(&self, set_name: &str, elements: &[(IpAddr, u64)]) -> Result<()> {
+        self.apply_script(&timed_element_commands(&self.table_name, set_name, elements)).await
+    }
+    
+    fn element_commands(&self, verb: &str, set_name: &str, elements: &[IpNet]) -> Vec<String> {
+        // 单条命令的元素过多时分批写入
+        const BATCH: usize = 1000;
+        
+        elements.chunks(BATCH)
+            .map(|chunk| {
+                let elements: Vec<String> = chunk.iter().map(|e| e.to_string()).collect();
+                format!(
+                    "{} element inet {} {} {{ {} }}",
+                    verb, self.table_name, set_name, elements.join(", ")
+                )
+            })
+            .collect()
     }
     
     async fn execute_nft_command(&self, command: &str) -> Result<()> {
@@ -167,16 +794,178 @@ impl NftablesManager {
         Ok(())
     }
     
+    async fn apply_script(&self, script: &[String]) -> Result<()> {
+        // 通过 nft -f 原子地应用一组命令
+        if script.is_empty() {
+            return Ok(());
+        }
+        
+        let mut child = Command::new("nft")
+            .args(["-f", "-"])
+            .stdin(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        
+        if let Some(mut stdin) = child.stdin.take() {
+            use tokio::io::AsyncWriteExt;
+            stdin.write_all(script.join("\n").as_bytes()).await?;
+            stdin.write_all(b"\n").await?;
+        }
+        
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("nft script failed: {}", stderr));
+        }
+        
+        Ok(())
+    }
+    
+    // 由期望规则集推算表中应有的对象，每条链的规则数按最后一次清空之后添加的规则计算
+    pub fn expected_state(&self, script: &[String]) -> TableState {
+        let mut state = TableState::default();
+        for line in script {
+            let words: Vec<&str> = line.split_whitespace().take(5).collect();
+            let [verb, kind, "inet", table, name] = words[..] else {
+                continue;
+            };
+            if table != self.table_name {
+                continue;
+            }
+            let name = name.to_string();
+            match (verb, kind) {
+                ("add", "chain") => {
+                    state.chains.entry(name).or_default();
+                }
+                ("flush", "chain") => {
+                    state.chains.insert(name, 0);
+                }
+                ("add", "rule") => *state.chains.entry(name).or_default() += 1,
+                ("add", "set" | "map") => {
+                    state.sets.insert(name);
+                }
+                ("add", "counter") => {
+                    state.counters.insert(name);
+                }
+                _ => {}
+            }
+        }
+        state
+    }
+    
+    // 读取表中的对象，不含集合元素；表不存在时返回 None
+    pub async fn read_state(&self) -> Result<Option<TableState>> {
+        let output = Command::new("nft")
+            .args(["-j", "-t", "list", "table", "inet", &self.table_name])
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("No such file or directory") {
+                return Ok(None);
+            }
+            return Err(anyhow::anyhow!("nft list table failed: {}", stderr));
+        }
+        
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        let mut state = TableState::default();
+        for item in json["nftables"].as_array().into_iter().flatten() {
+            if let Some(name) = item["chain"]["name"].as_str() {
+                state.chains.entry(name.to_string()).or_default();
+            } else if let Some(chain) = item["rule"]["chain"].as_str() {
+                *state.chains.entry(chain.to_string()).or_default() += 1;
+            } else if let Some(name) = item["set"]["name"].as_str().or(item["map"]["name"].as_str()) {
+                state.sets.insert(name.to_string());
+            } else if let Some(name) = item["counter"]["name"].as_str() {
+                state.counters.insert(name.to_string());
+            }
+        }
+        Ok(Some(state))
+    }
+    
+    // 列出缺失的对象和规则数不符的链，表中多出的对象(如外部程序创建的集合)不算差异
+    pub fn describe_drift(&self, expected: &TableState, actual: Option<&TableState>) -> Vec<String> {
+        let Some(actual) = actual else {
+            return vec![format!("表 inet {} 不存在", self.table_name)];
+        };
+        
+        let mut drift = Vec::new();
+        for (chain, rules) in &expected.chains {
+            match actual.chains.get(chain) {
+                None => drift.push(format!("缺少链 {}", chain)),
+                Some(actual) if actual != rules => {
+                    drift.push(format!("链 {} 有 {} 条规则，应为 {} 条", chain, actual, rules));
+                }
+                Some(_) => {}
+            }
+        }
+        for set in expected.sets.difference(&actual.sets) {
+            drift.push(format!("缺少集合 {}", set));
+        }
+        for counter in expected.counters.difference(&actual.counters) {
+            drift.push(format!("缺少计数器 {}", counter));
+        }
+        drift
+    }
+    
+    // 订阅 nftables 事件，本表发生变化时通知检查，外部修改可以在一秒内修复
+    pub async fn monitor(&self) -> Result<()> {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+        
+        let mut cmd = Command::new("nft")
+            .args(["-j", "monitor"])
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let Some(stdout) = cmd.stdout.take() else {
+            return Ok(());
+        };
+        
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            if !self.is_table_event(&line) {
+                continue;
+            }
+            while let Ok(Ok(Some(_))) = tokio::time::timeout(MONITOR_DEBOUNCE, lines.next_line()).await {}
+            self.table_changed.notify_one();
+        }
+        
+        Err(anyhow::anyhow!("nft monitor exited"))
+    }
+    
+    // 事件格式: {"delete": {"rule": {"family": "inet", "table": "mwan3", ...}}}，
+    // 表本身的事件中表名在 name 字段
+    fn is_table_event(&self, line: &str) -> bool {
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(line) else {
+            return false;
+        };
+        event.as_object()
+            .into_iter()
+            .flat_map(|event| event.values())
+            .filter_map(|objects| objects.as_object())
+            .flatten()
+            .any(|(kind, object)| {
+                let table = if kind == "table" { &object["name"] } else { &object["table"] };
+                object["family"] == "inet" && table == self.table_name.as_str()
+            })
+    }
+    
+    pub async fn table_changed(&self) {
+        self.table_changed.notified().await
+    }
+    
+    #[allow(dead_code)]
     pub async fn get_table_rules(&self) -> Result<String> {
         // 获取表规则占位
         let output = Command::new("nft")
-            .args(&["list", "table", "inet", &self.table_name])
+            .args(["list", "table", "inet", &self.table_name])
             .output()
             .await?;
         
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
     
+    #[allow(dead_code)]
     pub async fn backup_rules(&self, file_path: &str) -> Result<()> {
         // 备份规则占位
         let rules = self.get_table_rules().await?;
@@ -184,11 +973,12 @@ impl NftablesManager {
         Ok(())
     }
     
+    #[allow(dead_code)]
     pub async fn restore_rules(&self, file_path: &str) -> Result<()> {
         // 恢复规则占位
         let rules = tokio::fs::read_to_string(file_path).await?;
         let mut child = Command::new("nft")
-            .args(&["-f", "-"])
+            .args(["-f", "-"])
             .stdin(std::process::Stdio::piped())
             .spawn()?;
         
@@ -200,4 +990,106 @@ impl NftablesManager {
         child.wait().await?;
         Ok(())
     }
+}
+
+// add element 不会刷新已存在元素的超时，因此在同一事务中先确保元素存在、删除后再带超时写入；
+// 同一地址只保留最长的超时，避免重复删除同一元素
+fn timed_element_commands(table: &str, set_name: &str, elements: &[(IpAddr, u64)]) -> Vec<String> {
+    let mut timeouts: BTreeMap<IpAddr, u64> = BTreeMap::new();
+    for (addr, timeout) in elements {
+        let entry = timeouts.entry(*addr).or_default();
+        *entry = (*entry).max(*timeout);
+    }
+    if timeouts.is_empty() {
+        return Vec::new();
+    }
+    
+    let addrs: Vec<String> = timeouts.keys().map(|addr| addr.to_string()).collect();
+    let timed: Vec<String> = timeouts.iter()
+        .map(|(addr, timeout)| format!("{} timeout {}s", addr, timeout))
+        .collect();
+    vec![
+        format!("add element inet {} {} {{ {} }}", table, set_name, addrs.join(", ")),
+        format!("delete element inet {} {} {{ {} }}", table, set_name, addrs.join(", ")),
+        format!("add element inet {} {} {{ {} }}", table, set_name, timed.join(", ")),
+    ]
+}
+
+// 按权重把 BALANCE_SLOTS 个取值划分为连续区间，余数按最大余数法分配，返回 (出口, 起始, 结束)
+fn balance_slots<'a>(targets: &[&'a RouteTarget]) -> Vec<(&'a RouteTarget, u32, u32)> {
+    let total: u64 = targets.iter().map(|t| u64::from(t.weight)).sum();
+    if total == 0 {
+        return Vec::new();
+    }
+    let shares: Vec<(u64, u64)> = targets.iter()
+        .map(|t| {
+            let exact = u64::from(t.weight) * u64::from(BALANCE_SLOTS);
+            (exact / total, exact % total)
+        })
+        .collect();
+    let mut slots: Vec<u64> = shares.iter().map(|(slots, _)| *slots).collect();
+    let assigned: u64 = slots.iter().sum();
+    let mut order: Vec<usize> = (0..targets.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(shares[i].1));
+    for &i in order.iter().take((u64::from(BALANCE_SLOTS) - assigned) as usize) {
+        slots[i] += 1;
+    }
+    
+    let mut start = 0;
+    targets.iter()
+        .zip(slots)
+        .filter(|(_, slots)| *slots > 0)
+        .map(|(target, slots)| {
+            let end = start + slots as u32 - 1;
+            let range = (*target, start, end);
+            start = end + 1;
+            range
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn target(interface: &str, weight: u32) -> RouteTarget {
+        RouteTarget { interface: interface.to_string(), mark: 1, weight }
+    }
+    
+    #[test]
+    fn balance_slots_cover_all_values_in_proportion() {
+        let targets = [target("wan1", 300), target("wan2", 200), target("wan3", 100)];
+        let refs: Vec<&RouteTarget> = targets.iter().collect();
+        let ranges: Vec<(&str, u32, u32)> = balance_slots(&refs).into_iter()
+            .map(|(t, start, end)| (t.interface.as_str(), start, end))
+            .collect();
+        // 按比例分别为 500、333.3、166.7 个取值，剩余的一个分给余数最大的 wan3
+        assert_eq!(ranges, [("wan1", 0, 499), ("wan2", 500, 832), ("wan3", 833, 999)]);
+    }
+    
+    #[test]
+    fn balance_slots_single_and_empty() {
+        let single = target("wan1", 7);
+        assert_eq!(balance_slots(&[&single]).into_iter().map(|(_, s, e)| (s, e)).collect::<Vec<_>>(), [(0, BALANCE_SLOTS - 1)]);
+        assert!(balance_slots(&[]).is_empty());
+        
+        // 权重极小的接口可能分不到取值，但区间仍然连续且覆盖全部取值
+        let targets = [target("wan1", 100_000), target("wan2", 1)];
+        let refs: Vec<&RouteTarget> = targets.iter().collect();
+        let ranges = balance_slots(&refs);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].1, ranges[0].2), (0, BALANCE_SLOTS - 1));
+    }
+    
+    #[test]
+    fn timed_elements_are_replaced_to_refresh_timeout() {
+        let a: IpAddr = "1.1.1.1".parse().unwrap();
+        let b: IpAddr = "1.0.0.1".parse().unwrap();
+        assert_eq!(timed_element_commands("mwan3", "cn_v4", &[(a, 60), (b, 300), (a, 120)]), [
+            "add element inet mwan3 cn_v4 { 1.0.0.1, 1.1.1.1 }",
+            "delete element inet mwan3 cn_v4 { 1.0.0.1, 1.1.1.1 }",
+            "add element inet mwan3 cn_v4 { 1.0.0.1 timeout 300s, 1.1.1.1 timeout 120s }",
+        ]);
+        assert!(timed_element_commands("mwan3", "cn_v4", &[]).is_empty());
+    }
 }
\ No newline at end of file
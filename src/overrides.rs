@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+use crate::config::{parse_duration, Config, PolicyMember};
+use crate::fsutil::write_atomic_async;
+
+// 运行时覆盖规则，保存在状态目录中，配置重载和进程重启后仍然有效
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Override {
+    pub id: String,
+    #[serde(flatten)]
+    pub kind: OverrideKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum OverrideKind {
+    // 强制策略只使用指定接口
+    Force { policy: String, member: String },
+    // 从策略中排除成员，未指定策略时对所有策略生效
+    Exclude {
+        member: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        policy: Option<String>,
+    },
+    // 指定源地址段固定走某个接口
+    Pin { source: IpNet, interface: String },
+    // 排空接口：不再分配新连接，已有连接保持到结束或截止时间
+    Drain {
+        interface: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deadline: Option<DateTime<Local>>,
+    },
+}
+
+impl OverrideKind {
+    // 同一对象的新规则覆盖旧规则
+    fn replaces(&self, other: &OverrideKind) -> bool {
+        match (self, other) {
+            (OverrideKind::Force { policy: a, .. }, OverrideKind::Force { policy: b, .. }) => a == b,
+            (OverrideKind::Pin { source: a, .. }, OverrideKind::Pin { source: b, .. }) => a == b,
+            (OverrideKind::Drain { interface: a, .. }, OverrideKind::Drain { interface: b, .. }) => a == b,
+            _ => self == other,
+        }
+    }
+}
+
+impl Override {
+    pub fn new(kind: OverrideKind, expires: Option<DateTime<Local>>) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        Self { id, kind, expires }
+    }
+    
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        self.expires.is_none_or(|expires| expires > now)
+    }
+    
+    pub fn validate(&self, config: &Config) -> Result<()> {
+        match &self.kind {
+            OverrideKind::Force { policy, member } => {
+                if config.find_policy(policy).is_none() {
+                    return Err(anyhow::anyhow!("Policy not found: {}", policy));
+                }
+                match config.find_member(member) {
+                    None => return Err(anyhow::anyhow!("Unknown member: {}", member)),
+                    // 强制使用的嵌套策略不能再引用被覆盖的策略
+                    Some(PolicyMember::Policy(nested)) => {
+                        config.check_policy_cycle(nested, &mut vec![policy.as_str()], &mut HashSet::new())?;
+                    }
+                    Some(PolicyMember::Interface(_)) => {}
+                }
+            }
+            OverrideKind::Exclude { member, policy } => {
+                if let Some(policy) = policy.as_ref().filter(|p| config.find_policy(p).is_none()) {
+                    return Err(anyhow::anyhow!("Policy not found: {}", policy));
+                }
+                if config.find_member(member).is_none() {
+                    return Err(anyhow::anyhow!("Unknown member: {}", member));
+                }
+            }
+            OverrideKind::Pin { interface, .. } | OverrideKind::Drain { interface, .. } => {
+                if config.find_interface(interface).is_none() {
+                    return Err(anyhow::anyhow!("Interface not found: {}", interface));
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn describe(&self) -> String {
+        let action = match &self.kind {
+            OverrideKind::Force { policy, member } => format!("force {} -> {}", policy, member),
+            OverrideKind::Exclude { member, policy: Some(policy) } => format!("exclude {} from {}", member, policy),
+            OverrideKind::Exclude { member, policy: None } => format!("exclude {}", member),
+            OverrideKind::Pin { source, interface } => format!("pin {} -> {}", source, interface),
+            OverrideKind::Drain { interface, deadline: Some(deadline) } => {
+                format!("drain {} (deadline {})", interface, deadline.format("%Y-%m-%d %H:%M:%S"))
+            }
+            OverrideKind::Drain { interface, deadline: None } => format!("drain {}", interface),
+        };
+        match self.expires {
+            Some(expires) => format!("{} (expires {})", action, expires.format("%Y-%m-%d %H:%M:%S")),
+            None => action,
+        }
+    }
+}
+
+// 覆盖规则是否排除了策略中的某个成员
+pub fn is_excluded(overrides: &[Override], policy: &str, member: &str) -> bool {
+    overrides.iter().any(|o| matches!(
+        &o.kind,
+        OverrideKind::Exclude { member: m, policy: scope }
+            if m == member && scope.as_deref().is_none_or(|p| p == policy)
+    ))
+}
+
+// 策略被强制使用的接口
+pub fn forced_member<'a>(overrides: &'a [Override], policy: &str) -> Option<&'a str> {
+    overrides.iter().find_map(|o| match &o.kind {
+        OverrideKind::Force { policy: p, member } if p == policy => Some(member.as_str()),
+        _ => None,
+    })
+}
+
+// 接口的排空覆盖规则，返回截止时间
+pub fn drain_of<'a>(overrides: &'a [Override], interface: &str) -> Option<&'a Option<DateTime<Local>>> {
+    overrides.iter().find_map(|o| match &o.kind {
+        OverrideKind::Drain { interface: i, deadline } if i == interface => Some(deadline),
+        _ => None,
+    })
+}
+
+pub struct OverrideStore {
+    path: PathBuf,
+}
+
+impl OverrideStore {
+    pub fn new(state_dir: &str) -> Self {
+        Self {
+            path: Path::new(state_dir).join("overrides.yaml"),
+        }
+    }
+    
+    pub async fn load(&self) -> Result<Vec<Override>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(serde_yaml::from_str::<Option<Vec<Override>>>(&content)?.unwrap_or_default()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    pub async fn save(&self, overrides: &[Override]) -> Result<()> {
+        write_atomic_async(&self.path, serde_yaml::to_string(overrides)?).await
+    }
+    
+    // 读取当前有效的覆盖规则
+    pub async fn active(&self, now: DateTime<Local>) -> Result<Vec<Override>> {
+        Ok(self.load().await?
+            .into_iter()
+            .filter(|o| o.is_active(now))
+            .collect())
+    }
+    
+    pub async fn add(&self, entry: Override) -> Result<()> {
+        let now = Local::now();
+        let mut overrides: Vec<Override> = self.load().await?
+            .into_iter()
+            .filter(|o| o.is_active(now) && !o.kind.replaces(&entry.kind))
+            .collect();
+        overrides.push(entry);
+        self.save(&overrides).await
+    }
+    
+    // 删除指定 id 的覆盖规则，id 为 "all" 时全部删除，返回删除的数量
+    pub async fn remove(&self, id: &str) -> Result<usize> {
+        let overrides = self.load().await?;
+        let before = overrides.len();
+        let remaining: Vec<Override> = overrides.into_iter()
+            .filter(|o| id != "all" && o.id != id)
+            .collect();
+        let removed = before - remaining.len();
+        self.save(&remaining).await?;
+        Ok(removed)
+    }
+}
+
+// 处理 override 子命令
+pub async fn run_command(config: &Config, matches: &clap::ArgMatches) -> Result<()> {
+    let store = OverrideStore::new(&config.global.state_dir);
+    
+    let (name, sub) = matches.subcommand()
+        .ok_or_else(|| anyhow::anyhow!("Missing override command"))?;
+    let kind = match name {
+        "force" => OverrideKind::Force {
+            policy: sub.get_one::<String>("policy").unwrap().clone(),
+            member: sub.get_one::<String>("member").unwrap().clone(),
+        },
+        "exclude" => OverrideKind::Exclude {
+            member: sub.get_one::<String>("member").unwrap().clone(),
+            policy: sub.get_one::<String>("policy").cloned(),
+        },
+        "pin" => OverrideKind::Pin {
+            source: sub.get_one::<String>("source").unwrap().parse()?,
+            interface: sub.get_one::<String>("interface").unwrap().clone(),
+        },
+        "drain" => OverrideKind::Drain {
+            interface: sub.get_one::<String>("interface").unwrap().clone(),
+            deadline: match sub.get_one::<String>("deadline") {
+                Some(value) => Some(Local::now() + chrono::Duration::from_std(parse_duration(value)?)?),
+                None => None,
+            },
+        },
+        "remove" => {
+            let id = sub.get_one::<String>("id").unwrap();
+            let removed = store.remove(id).await?;
+            println!("已删除 {} 条覆盖规则", removed);
+            return Ok(());
+        }
+        "list" => {
+            let now = Local::now();
+            for entry in store.load().await?.iter().filter(|o| o.is_active(now)) {
+                println!("{}  {}", entry.id, entry.describe());
+            }
+            return Ok(());
+        }
+        _ => return Err(anyhow::anyhow!("Unknown override command: {}", name)),
+    };
+    
+    let expires = match sub.get_one::<String>("expire") {
+        Some(value) => Some(Local::now() + chrono::Duration::from_std(parse_duration(value)?)?),
+        None => None,
+    };
+    
+    let entry = Override::new(kind, expires);
+    entry.validate(config)?;
+    
+    println!("{}  {}", entry.id, entry.describe());
+    store.add(entry).await
+}
\ No newline at end of file
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+use crate::config::{parse_size, Config, QuotaAction, QuotaConfig};
+use crate::fsutil::write_atomic_async;
+use crate::stats::{format_bytes, read_sysfs_counters};
+
+// 用量变化后写入状态文件的最短间隔，计数器读数一并保存，重启后不会漏计
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+// 接口在当前计费周期内的流量用量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    // 当前计费周期的开始时间
+    pub cycle: DateTime<Local>,
+    // 本周期已使用的字节数
+    pub used: u64,
+    // 上次采样时接口的收发字节数合计
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct QuotaTracker {
+    usage: HashMap<String, QuotaUsage>,
+    loaded: bool,
+    dirty: bool,
+    last_saved: Option<Instant>,
+}
+
+impl QuotaTracker {
+    // 采样配置了配额的接口，累计用量并在跨过告警线或上限时记录日志
+    pub async fn update(&mut self, config: &Config, now: DateTime<Local>) -> Result<()> {
+        if !self.loaded {
+            self.usage = load(&config.global.state_dir).await?;
+            self.loaded = true;
+        }
+        self.usage.retain(|name, _| config.find_interface(name).is_some_and(|i| i.quota.is_some()));
+        
+        for interface in &config.interfaces {
+            let Some(quota) = &interface.quota else {
+                continue;
+            };
+            let limit = parse_size(&quota.limit)?;
+            let cycle = cycle_start(now, quota.cycle_start);
+            let usage = self.usage.entry(interface.name.clone()).or_insert_with(|| {
+                QuotaUsage { cycle, used: 0, counter: None }
+            });
+            
+            if usage.cycle != cycle {
+                tracing::info!(
+                    "接口 {} 进入新的计费周期，上一周期用量 {}",
+                    interface.name, format_bytes(usage.used)
+                );
+                usage.cycle = cycle;
+                usage.used = 0;
+                self.dirty = true;
+            }
+            
+            // 接口不存在(如拨号断开)时跳过，重新出现后从新的读数开始计算
+            let Ok(counters) = read_sysfs_counters(&interface.interface_name).await else {
+                usage.counter = None;
+                continue;
+            };
+            let total = counters.rx_bytes + counters.tx_bytes;
+            let before = usage.used;
+            if let Some(last) = usage.counter {
+                // 读数变小说明接口被重建，计数器从零开始
+                usage.used += if total >= last { total - last } else { total };
+            }
+            if usage.counter != Some(total) {
+                usage.counter = Some(total);
+                self.dirty = true;
+            }
+            
+            let warning = limit / 100 * quota.warning as u64;
+            if before < warning && usage.used >= warning && usage.used < limit {
+                tracing::warn!(
+                    "接口 {} 流量已使用 {}，达到配额的 {}%",
+                    interface.name, format_bytes(usage.used), quota.warning
+                );
+            }
+            if before < limit && usage.used >= limit {
+                tracing::warn!(
+                    "接口 {} 流量配额已用尽 ({})，{}",
+                    interface.name,
+                    format_bytes(limit),
+                    match quota.action {
+                        QuotaAction::Exclude => "已从策略中排除",
+                        QuotaAction::LastResort => "仅在没有其他可用接口时使用",
+                    }
+                );
+            }
+        }
+        
+        if self.dirty && self.last_saved.is_none_or(|t| t.elapsed() >= SAVE_INTERVAL) {
+            save(&config.global.state_dir, &self.usage).await?;
+            self.dirty = false;
+            self.last_saved = Some(Instant::now());
+        }
+        
+        Ok(())
+    }
+    
+    pub fn usage(&self, interface: &str) -> Option<&QuotaUsage> {
+        self.usage.get(interface)
+    }
+    
+    // 配额已用尽的接口及其处理方式
+    pub fn exhausted(&self, config: &Config) -> HashMap<String, QuotaAction> {
+        config.interfaces.iter()
+            .filter_map(|interface| {
+                let quota = interface.quota.as_ref()?;
+                let usage = self.usage.get(&interface.name)?;
+                is_exhausted(quota, usage).then(|| (interface.name.clone(), quota.action))
+            })
+            .collect()
+    }
+}
+
+pub fn is_exhausted(quota: &QuotaConfig, usage: &QuotaUsage) -> bool {
+    parse_size(&quota.limit).is_ok_and(|limit| usage.used >= limit)
+}
+
+// 包含当前时间的计费周期的开始时间
+fn cycle_start(now: DateTime<Local>, day: u32) -> DateTime<Local> {
+    let today = now.date_naive();
+    let this_month = NaiveDate::from_ymd_opt(today.year(), today.month(), day)
+        .expect("cycle-start is validated to be 1-28");
+    let start = if today >= this_month {
+        this_month
+    } else {
+        let previous = this_month.with_day(1).and_then(|d| d.pred_opt()).unwrap_or(this_month);
+        previous.with_day(day).unwrap_or(previous)
+    };
+    
+    Local.from_local_datetime(&start.and_time(Default::default()))
+        .earliest()
+        .unwrap_or(now)
+}
+
+async fn load(state_dir: &str) -> Result<HashMap<String, QuotaUsage>> {
+    let path = Path::new(state_dir).join("quota.yaml");
+    match tokio::fs::read_to_string(&path).await {
+        Ok(content) => Ok(serde_yaml::from_str::<Option<HashMap<String, QuotaUsage>>>(&content)?.unwrap_or_default()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn save(state_dir: &str, usage: &HashMap<String, QuotaUsage>) -> Result<()> {
+    let path = Path::new(state_dir).join("quota.yaml");
+    write_atomic_async(path, serde_yaml::to_string(usage)?).await
+}
\ No newline at end of file
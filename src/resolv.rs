@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use anyhow::Result;
+
+use crate::config::{Config, Interface};
+
+// 读取 resolv.conf.auto 中按接口分节记录的 DNS 服务器：
+// "# Interface wan" 之后的 nameserver 属于接口 wan，文件不存在时返回空
+pub async fn learn_servers(path: &str) -> Result<HashMap<String, Vec<IpAddr>>> {
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    
+    let mut servers: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    let mut section = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("# Interface ") {
+            section = Some(name.trim().to_string());
+        } else if let (Some(section), Some(server)) = (&section, line.strip_prefix("nameserver")) {
+            // 带作用域的链路本地地址(fe80::1%eth0)无法用于规则匹配
+            if let Ok(addr) = server.trim().parse::<IpAddr>() {
+                servers.entry(section.clone()).or_default().push(addr);
+            }
+        }
+    }
+    Ok(servers)
+}
+
+// 节名是系统中的逻辑接口名，如 wan、wan_6，对应 pppoe-wan 等设备
+fn section_matches(section: &str, interface: &Interface) -> bool {
+    let logical = section.strip_suffix("_6").unwrap_or(section);
+    logical == interface.name
+        || logical == interface.interface_name
+        || format!("pppoe-{}", logical) == interface.interface_name
+}
+
+// 各接口的 DNS 服务器：配置的服务器加上从系统中读取的服务器
+pub fn interface_servers(config: &Config, learned: &HashMap<String, Vec<IpAddr>>) -> HashMap<String, Vec<IpAddr>> {
+    config.interfaces.iter()
+        .filter(|interface| interface.enabled)
+        .filter_map(|interface| {
+            let mut servers = interface.dns.clone();
+            for (section, addrs) in learned {
+                if section_matches(section, interface) {
+                    servers.extend(addrs.iter().copied());
+                }
+            }
+            servers.sort();
+            servers.dedup();
+            (!servers.is_empty()).then(|| (interface.name.clone(), servers))
+        })
+        .collect()
+}
\ No newline at end of file
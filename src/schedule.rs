@@ -0,0 +1,226 @@
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveTime, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+use crate::config::{Config, Family};
+use crate::overrides::{Override, OverrideKind};
+
+// 按星期和时间段生效的计划，窗口内切换策略或排空接口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    // 生效的星期，如 ["mon", "tue"]，为空表示每天
+    #[serde(default)]
+    pub days: Vec<String>,
+    // 开始和结束时间 "HH:MM"，结束早于开始表示跨越午夜
+    pub start: String,
+    pub end: String,
+    // IANA 时区名如 "Asia/Shanghai"，或固定偏移如 "+08:00"，未设置时使用系统本地时区
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    // 窗口内使用的策略，未设置 policy-v6 时 IPv6 也使用该策略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    // 窗口内 IPv6 使用的策略
+    #[serde(rename = "policy-v6", default, skip_serializing_if = "Option::is_none")]
+    pub policy_v6: Option<String>,
+    // 窗口内排空的接口
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drain: Option<String>,
+}
+
+impl Schedule {
+    pub fn validate(&self, config: &Config) -> Result<()> {
+        self.parse_days()?;
+        self.parse_times()?;
+        self.parse_timezone()?;
+        
+        let switches_policy = self.policy.is_some() || self.policy_v6.is_some();
+        match &self.drain {
+            None if switches_policy => {
+                for policy in self.policy.iter().chain(&self.policy_v6) {
+                    if config.find_policy(policy).is_none() {
+                        return Err(anyhow::anyhow!("Schedule {} references unknown policy: {}", self.name, policy));
+                    }
+                }
+            }
+            Some(interface) if !switches_policy => {
+                if config.find_interface(interface).is_none() {
+                    return Err(anyhow::anyhow!("Schedule {} references unknown interface: {}", self.name, interface));
+                }
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Schedule {} must set exactly one of policy or drain", self.name
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        let (Ok(days), Ok((start, end)), Ok(timezone)) =
+            (self.parse_days(), self.parse_times(), self.parse_timezone())
+        else {
+            return false;
+        };
+        
+        let (weekday, time) = match timezone {
+            Some(Zone::Named(tz)) => {
+                let local = now.with_timezone(&tz);
+                (local.weekday(), local.time())
+            }
+            Some(Zone::Fixed(offset)) => {
+                let local = now.with_timezone(&offset);
+                (local.weekday(), local.time())
+            }
+            None => (now.weekday(), now.time()),
+        };
+        let matches_day = |day: Weekday| days.is_empty() || days.contains(&day);
+        
+        if start <= end {
+            matches_day(weekday) && time >= start && time < end
+        } else {
+            // 跨午夜的窗口属于开始那一天
+            (matches_day(weekday) && time >= start) || (matches_day(weekday.pred()) && time < end)
+        }
+    }
+    
+    fn parse_days(&self) -> Result<Vec<Weekday>> {
+        self.days.iter()
+            .map(|day| day.parse::<Weekday>()
+                .map_err(|_| anyhow::anyhow!("Schedule {} has invalid day: {}", self.name, day)))
+            .collect()
+    }
+    
+    fn parse_times(&self) -> Result<(NaiveTime, NaiveTime)> {
+        let parse = |value: &str| NaiveTime::parse_from_str(value, "%H:%M")
+            .map_err(|_| anyhow::anyhow!("Schedule {} has invalid time: {}", self.name, value));
+        let (start, end) = (parse(&self.start)?, parse(&self.end)?);
+        // 开始和结束相同的窗口为空，永远不会生效
+        if start == end {
+            return Err(anyhow::anyhow!("Schedule {} has an empty window: {} - {}", self.name, self.start, self.end));
+        }
+        Ok((start, end))
+    }
+    
+    // 优先按 IANA 时区名解析(随夏令时变化)，否则按固定偏移解析
+    fn parse_timezone(&self) -> Result<Option<Zone>> {
+        self.timezone.as_deref()
+            .map(|tz| tz.parse::<Tz>().map(Zone::Named)
+                .or_else(|_| tz.parse::<FixedOffset>().map(Zone::Fixed))
+                .map_err(|_| anyhow::anyhow!("Schedule {} has invalid timezone: {}", self.name, tz)))
+            .transpose()
+    }
+}
+
+enum Zone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+// 当前生效的计划
+pub fn active_schedules(config: &Config, now: DateTime<Local>) -> Vec<&Schedule> {
+    config.schedules.iter()
+        .filter(|schedule| schedule.is_active(now))
+        .collect()
+}
+
+// 指定地址族当前应使用的策略：生效计划指定的策略优先，否则使用全局默认策略，
+// IPv6 未单独指定时与 IPv4 相同
+pub fn effective_policy(config: &Config, now: DateTime<Local>, family: Family) -> &str {
+    active_schedules(config, now).into_iter()
+        .find_map(|schedule| match family {
+            Family::V4 => schedule.policy.as_deref(),
+            Family::V6 => schedule.policy_v6.as_deref().or(schedule.policy.as_deref()),
+        })
+        .unwrap_or(match family {
+            Family::V4 => &config.global.policy,
+            Family::V6 => config.global.policy_v6.as_deref().unwrap_or(&config.global.policy),
+        })
+}
+
+// 将生效计划中的排空转换为覆盖规则，与运行时覆盖规则统一处理
+pub fn scheduled_overrides(config: &Config, now: DateTime<Local>) -> Vec<Override> {
+    active_schedules(config, now).into_iter()
+        .filter_map(|schedule| {
+            let interface = schedule.drain.clone()?;
+            Some(Override {
+                id: format!("schedule:{}", schedule.name),
+                kind: OverrideKind::Drain { interface, deadline: None },
+                expires: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn schedule(days: &[&str], start: &str, end: &str, timezone: &str) -> Schedule {
+        Schedule {
+            name: "test".to_string(),
+            days: days.iter().map(|d| d.to_string()).collect(),
+            start: start.to_string(),
+            end: end.to_string(),
+            timezone: Some(timezone.to_string()),
+            policy: Some("fallback".to_string()),
+            policy_v6: None,
+            drain: None,
+        }
+    }
+    
+    fn at(time: &str) -> DateTime<Local> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Local)
+    }
+    
+    #[test]
+    fn window_across_midnight_belongs_to_start_day() {
+        // 2024-01-05 是星期五
+        let s = schedule(&["fri"], "22:00", "02:00", "+00:00");
+        assert!(!s.is_active(at("2024-01-05T21:59:00Z")));
+        assert!(s.is_active(at("2024-01-05T22:00:00Z")));
+        assert!(s.is_active(at("2024-01-06T01:59:00Z")));
+        assert!(!s.is_active(at("2024-01-06T02:00:00Z")));
+        // 星期六晚上不属于星期五的窗口
+        assert!(!s.is_active(at("2024-01-06T23:00:00Z")));
+        // 星期五凌晨属于星期四的窗口
+        assert!(!s.is_active(at("2024-01-05T01:00:00Z")));
+    }
+    
+    #[test]
+    fn window_uses_schedule_timezone() {
+        let s = schedule(&["mon"], "09:00", "18:00", "+08:00");
+        // 2024-01-08 星期一 09:30 +08:00
+        assert!(s.is_active(at("2024-01-08T01:30:00Z")));
+        assert!(!s.is_active(at("2024-01-08T10:00:00Z")));
+        // UTC 仍是星期日，+08:00 已是星期一
+        assert!(s.is_active(at("2024-01-07T23:30:00-02:00")));
+    }
+    
+    #[test]
+    fn named_timezone_follows_daylight_saving() {
+        let s = schedule(&[], "09:00", "10:00", "Europe/Berlin");
+        // 冬令时 UTC+1，夏令时 UTC+2
+        assert!(s.is_active(at("2024-01-15T08:30:00Z")));
+        assert!(!s.is_active(at("2024-07-15T08:30:00Z")));
+        assert!(s.is_active(at("2024-07-15T07:30:00Z")));
+    }
+    
+    #[test]
+    fn invalid_timezone_is_never_active() {
+        let s = schedule(&[], "00:00", "23:59", "Mars/Olympus");
+        assert!(s.parse_timezone().is_err());
+        assert!(!s.is_active(at("2024-01-15T08:30:00Z")));
+    }
+    
+    #[test]
+    fn empty_window_is_rejected() {
+        let s = schedule(&[], "08:00", "08:00", "+00:00");
+        assert_eq!(s.parse_times().unwrap_err().to_string(), "Schedule test has an empty window: 08:00 - 08:00");
+        assert!(!s.is_active(at("2024-01-15T08:00:00Z")));
+    }
+}
\ No newline at end of file
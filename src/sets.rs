@@ -0,0 +1,188 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use ipnet::IpNet;
+use tokio::sync::{Notify, RwLock};
+use anyhow::Result;
+
+use crate::cidr_sources;
+use crate::config::{Config, Family, NftablesSet};
+use crate::connected::parse_prefix;
+use crate::nftables::NftablesManager;
+
+// 受管集合及其来源文件的同步状态
+struct LoadedSet {
+    file: String,
+    // 上次加载时文件的修改时间和大小
+    modified: Option<(SystemTime, u64)>,
+    // 文件不可读，已记录过告警
+    unreadable: bool,
+    // 已写入集合的元素，未知时(如启动后首次加载)从 nftables 中读取
+    elements: Option<BTreeSet<IpNet>>,
+}
+
+// 从本地网段列表文件填充受管集合，文件变化后按差异增删元素
+#[derive(Default)]
+pub struct SetLoader {
+    sets: HashMap<String, LoadedSet>,
+}
+
+impl SetLoader {
+    pub async fn sync(&mut self, config: &Config, nftables: &NftablesManager) {
+        let managed: Vec<&NftablesSet> = config.interfaces.iter()
+            .flat_map(|i| &i.nftables_sets)
+            .filter(|set| set.managed && set.file.is_some())
+            .collect();
+        self.sets.retain(|name, _| managed.iter().any(|set| &set.name == name));
+        
+        for set in managed {
+            if let Err(e) = self.sync_set(set, nftables).await {
+                tracing::warn!("更新集合 {} 失败: {}", set.name, e);
+            }
+        }
+    }
+    
+    // 集合被外部删除后重建时为空，下次同步重新写入全部元素
+    pub fn reset(&mut self) {
+        self.sets.clear();
+    }
+    
+    async fn sync_set(&mut self, set: &NftablesSet, nftables: &NftablesManager) -> Result<()> {
+        let (Some(file), Some(family)) = (&set.file, set.family) else {
+            return Ok(());
+        };
+        let entry = self.sets.entry(set.name.clone()).or_insert_with(|| LoadedSet {
+            file: file.clone(),
+            modified: None,
+            unreadable: false,
+            elements: None,
+        });
+        
+        // 文件暂时不可读时保留集合中现有的元素
+        let modified = match tokio::fs::metadata(file).await {
+            Ok(metadata) => (metadata.modified()?, metadata.len()),
+            Err(e) => {
+                if !entry.unreadable {
+                    tracing::warn!("集合 {} 的来源文件 {} 不可读，保留现有元素: {}", set.name, file, e);
+                    entry.unreadable = true;
+                }
+                return Ok(());
+            }
+        };
+        entry.unreadable = false;
+        if entry.file == *file && entry.modified == Some(modified) {
+            return Ok(());
+        }
+        
+        let content = tokio::fs::read_to_string(file).await?;
+        let (elements, skipped) = parse_list(&content, family);
+        if skipped > 0 {
+            tracing::warn!("集合 {} 的来源文件 {} 中有 {} 行无效或地址族不符，已忽略", set.name, file, skipped);
+        }
+        
+        let current = match entry.elements.take() {
+            Some(current) => Some(current),
+            None => nftables.set_elements(&set.name).await?.map(BTreeSet::from_iter),
+        };
+        match current {
+            Some(current) => {
+                let add: Vec<IpNet> = elements.difference(&current).copied().collect();
+                let delete: Vec<IpNet> = current.difference(&elements).copied().collect();
+                if !add.is_empty() || !delete.is_empty() {
+                    nftables.update_set_elements(&set.name, &add, &delete).await?;
+                    tracing::info!(
+                        "集合 {} 已更新: 新增 {} 条，删除 {} 条，共 {} 条",
+                        set.name, add.len(), delete.len(), elements.len()
+                    );
+                }
+            }
+            None => {
+                // 集合中有无法按网段比较的元素，整体替换
+                let all: Vec<IpNet> = elements.iter().copied().collect();
+                nftables.replace_set_elements(&set.name, &all).await?;
+                tracing::info!("集合 {} 已重新加载，共 {} 条", set.name, elements.len());
+            }
+        }
+        
+        entry.file = file.clone();
+        entry.modified = Some(modified);
+        entry.elements = Some(elements);
+        Ok(())
+    }
+}
+
+// 来源文件更新后在后台重新生成网段列表文件，解析来源不阻塞规则同步，
+// 生成完成后通知负载均衡器按差异同步集合
+pub struct SetGenerator {
+    config: Arc<RwLock<Config>>,
+    generated: Notify,
+}
+
+impl SetGenerator {
+    pub fn new(config: Arc<RwLock<Config>>) -> Self {
+        Self {
+            config,
+            generated: Notify::new(),
+        }
+    }
+    
+    // 未开启 refresh 时任务立即结束
+    pub async fn start(&self) -> Result<()> {
+        let config = self.config.read().await;
+        if !config.global.cidr_sources.refresh {
+            return Ok(());
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(config.global.reconcile_interval));
+        // 生成的文件都比来源文件新时不必在启动时重新生成
+        let sources = cidr_sources::sources_modified(&config.global.cidr_sources);
+        let mut generated = sources.filter(|sources| {
+            cidr_sources::outputs_modified(&config).is_some_and(|outputs| outputs >= *sources)
+        });
+        drop(config);
+        
+        loop {
+            interval.tick().await;
+            let snapshot = self.config.read().await.clone();
+            let modified = cidr_sources::sources_modified(&snapshot.global.cidr_sources);
+            if modified.is_none() || modified == generated {
+                continue;
+            }
+            
+            let result = tokio::task::spawn_blocking(move || cidr_sources::generate_all(&snapshot, None)).await?;
+            match result {
+                Ok(results) => {
+                    for (name, count, _) in results.iter().filter(|(_, _, changed)| *changed) {
+                        tracing::info!("已重新生成集合 {} 的网段列表，共 {} 条", name, count);
+                    }
+                    generated = modified;
+                    if results.iter().any(|(_, _, changed)| *changed) {
+                        self.generated.notify_one();
+                    }
+                }
+                Err(e) => tracing::warn!("生成网段列表失败: {}", e),
+            }
+        }
+    }
+    
+    pub async fn generated(&self) {
+        self.generated.notified().await
+    }
+}
+
+// 解析网段列表：每行一个网段或地址，# 之后为注释，返回合并后的网段和被忽略的行数
+fn parse_list(content: &str, family: Family) -> (BTreeSet<IpNet>, usize) {
+    let mut prefixes = Vec::new();
+    let mut skipped = 0;
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        match parse_prefix(line) {
+            Some(prefix @ IpNet::V4(_)) if family == Family::V4 => prefixes.push(prefix.trunc()),
+            Some(prefix @ IpNet::V6(_)) if family == Family::V6 => prefixes.push(prefix.trunc()),
+            _ => skipped += 1,
+        }
+    }
+    (IpNet::aggregate(&prefixes).into_iter().collect(), skipped)
+}
\ No newline at end of file
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use chrono::{DateTime, FixedOffset, Local};
+use anyhow::Result;
+
+use crate::config::{parse_duration, Config, Family};
+use crate::health_check::InterfaceHealth;
+use crate::load_balancer::{resolve_policy, step_adaptive_factors, PolicyContext, PolicyDecision};
+use crate::nftables::NftablesManager;
+use crate::resolv;
+use crate::schedule;
+
+// 未指定延迟时模拟链路的默认延迟
+const DEFAULT_LATENCY: Duration = Duration::from_millis(20);
+
+// 时间线中的单个事件，例如 "t=10s wan1 down"
+#[derive(Debug, Clone)]
+struct TimelineEvent {
+    at: Duration,
+    interface: String,
+    action: LinkAction,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LinkAction {
+    Up,
+    Down,
+    Latency(Duration),
+}
+
+impl fmt::Display for LinkAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkAction::Up => write!(f, "up"),
+            LinkAction::Down => write!(f, "down"),
+            LinkAction::Latency(latency) => write!(f, "latency {}ms", latency.as_millis()),
+        }
+    }
+}
+
+// 模拟链路状态，健康检测结果由它决定
+struct SimulatedLink {
+    up: bool,
+    latency: Duration,
+}
+
+pub async fn run(config: &Config, timeline_path: &str, start: DateTime<FixedOffset>) -> Result<()> {
+    let content = tokio::fs::read_to_string(timeline_path).await?;
+    let mut events = parse_timeline(&content)?;
+    for event in &events {
+        if config.find_interface(&event.interface).is_none() {
+            return Err(anyhow::anyhow!("Unknown interface in timeline: {}", event.interface));
+        }
+    }
+    events.sort_by_key(|e| e.at);
+    
+    let thresholds = &config.global.health_check;
+    let interval = Duration::from_secs(thresholds.interval);
+    
+    // 最后一个事件之后继续运行，直到健康状态足以收敛
+    let settle = interval * (thresholds.fail_threshold.max(thresholds.succ_threshold) + 1);
+    let end = events.last().map(|e| e.at).unwrap_or_default() + settle;
+    
+    let mut links: HashMap<String, SimulatedLink> = config.interfaces.iter()
+        .map(|i| (i.name.clone(), SimulatedLink { up: true, latency: DEFAULT_LATENCY }))
+        .collect();
+    let mut health: HashMap<String, InterfaceHealth> = HashMap::new();
+    let mut adaptive = HashMap::new();
+    // 模拟时不读取系统中的集合，只使用配置中指定了地址族的集合
+    let set_families = config.set_families();
+    // 模拟时不读取系统的 resolv.conf，只使用配置的 DNS 服务器
+    let dns_servers = resolv::interface_servers(config, &HashMap::new());
+    let nftables = NftablesManager::new(&config.global.nftables);
+    
+    let mut previous_schedules: Vec<&str> = Vec::new();
+    let mut previous_policy = "";
+    let mut previous_decision: Option<PolicyDecision> = None;
+    let mut previous_script: Vec<String> = Vec::new();
+    let mut pending = events.iter().peekable();
+    let mut now = Duration::ZERO;
+    
+    // 虚拟时钟按健康检测间隔推进
+    while now <= end {
+        while let Some(event) = pending.next_if(|e| e.at <= now) {
+            let link = links.get_mut(&event.interface).expect("validated interface");
+            match event.action {
+                LinkAction::Up => link.up = true,
+                LinkAction::Down => link.up = false,
+                LinkAction::Latency(latency) => link.latency = latency,
+            }
+            println!("[t={}s] 事件: {} {}", now.as_secs(), event.interface, event.action);
+        }
+        
+        for interface in config.interfaces.iter().filter(|i| i.enabled) {
+            let link = &links[&interface.name];
+            let state = health.entry(interface.name.clone()).or_insert_with(InterfaceHealth::new);
+            let was_online = state.is_online;
+            state.record_check(link.up.then_some(link.latency), thresholds);
+            if state.is_online != was_online {
+                println!(
+                    "[t={}s] 健康状态: {} {}",
+                    now.as_secs(), interface.name, if state.is_online { "上线" } else { "下线" }
+                );
+            }
+        }
+        
+        // 计划按虚拟时钟对应的时间评估
+        let wall_clock = (start + chrono::Duration::from_std(now)?).with_timezone(&Local);
+        let shown = wall_clock.with_timezone(start.offset());
+        let active: Vec<&str> = schedule::active_schedules(config, wall_clock).into_iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        for name in active.iter().filter(|name| !previous_schedules.contains(name)) {
+            println!("[t={}s] 计划 {} 已生效 ({})", now.as_secs(), name, shown.format("%a %H:%M"));
+        }
+        for name in previous_schedules.iter().filter(|name| !active.contains(name)) {
+            println!("[t={}s] 计划 {} 已结束 ({})", now.as_secs(), name, shown.format("%a %H:%M"));
+        }
+        previous_schedules = active;
+        
+        let policy_name = schedule::effective_policy(config, wall_clock, Family::V4);
+        let policy = config.find_policy(policy_name)
+            .ok_or_else(|| anyhow::anyhow!("Policy not found: {}", policy_name))?;
+        let overrides = schedule::scheduled_overrides(config, wall_clock);
+        
+        step_adaptive_factors(config, &health, &HashMap::new(), &mut adaptive);
+        let context = PolicyContext {
+            config,
+            family: Family::V4,
+            health: &health,
+            adaptive: &adaptive,
+            overrides: &overrides,
+            quota_exhausted: &HashMap::new(),
+            set_families: &set_families,
+        };
+        let decision = resolve_policy(&context, policy);
+        
+        if previous_policy != policy_name || previous_decision.as_ref() != Some(&decision) {
+            let targets: Vec<String> = decision.targets.iter()
+                .map(|t| format!("{}({})", t.interface, t.display_weight()))
+                .collect();
+            println!(
+                "[t={}s] 策略 {} 决策: {}",
+                now.as_secs(),
+                policy.name(),
+                if targets.is_empty() { "无可用接口".to_string() } else { targets.join(", ") }
+            );
+        }
+        
+        let decisions = std::slice::from_ref(&decision);
+        let mut script = nftables.render_ruleset(&config.interfaces, decisions, &config.global.bypass, &dns_servers);
+        script.extend(nftables.render_routes(decisions));
+        if script != previous_script {
+            print_script_diff(&previous_script, &script);
+        }
+        
+        previous_policy = policy_name;
+        previous_decision = Some(decision);
+        previous_script = script;
+        now += interval;
+    }
+    
+    Ok(())
+}
+
+fn parse_timeline(content: &str) -> Result<Vec<TimelineEvent>> {
+    let mut events = Vec::new();
+    
+    for (index, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        
+        // 支持 "t=10s wan1 down" 以及逗号分隔的多个事件
+        for entry in line.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let event = parse_event(entry)
+                .map_err(|e| anyhow::anyhow!("Timeline line {}: {}", index + 1, e))?;
+            events.push(event);
+        }
+    }
+    
+    Ok(events)
+}
+
+fn parse_event(entry: &str) -> Result<TimelineEvent> {
+    let parts: Vec<&str> = entry.split_whitespace().collect();
+    let (time, interface, action) = match parts.as_slice() {
+        [time, interface, action @ ..] if !action.is_empty() => (*time, *interface, action),
+        _ => return Err(anyhow::anyhow!("Invalid event: {}", entry)),
+    };
+    
+    let at = parse_duration(time.strip_prefix("t=").unwrap_or(time))?;
+    let action = match action {
+        ["up"] => LinkAction::Up,
+        ["down"] => LinkAction::Down,
+        ["latency", value] => LinkAction::Latency(parse_duration(value)?),
+        _ => return Err(anyhow::anyhow!("Invalid event action: {}", entry)),
+    };
+    
+    Ok(TimelineEvent {
+        at,
+        interface: interface.to_string(),
+        action,
+    })
+}
+
+fn print_script_diff(previous: &[String], current: &[String]) {
+    for line in previous.iter().filter(|l| !current.contains(l)) {
+        println!("    - {}", line);
+    }
+    for line in current.iter().filter(|l| !previous.contains(l)) {
+        println!("    + {}", line);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::nftables::NftablesManager;
+
+// /sys/class/net/<ifname>/statistics 中的计数器
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+}
+
+pub async fn read_sysfs_counters(interface_name: &str) -> Result<InterfaceCounters> {
+    let base = format!("/sys/class/net/{}/statistics", interface_name);
+    
+    Ok(InterfaceCounters {
+        rx_bytes: read_counter(&base, "rx_bytes").await?,
+        tx_bytes: read_counter(&base, "tx_bytes").await?,
+        rx_packets: read_counter(&base, "rx_packets").await?,
+        tx_packets: read_counter(&base, "tx_packets").await?,
+    })
+}
+
+async fn read_counter(base: &str, name: &str) -> Result<u64> {
+    let content = tokio::fs::read_to_string(format!("{}/{}", base, name)).await?;
+    Ok(content.trim().parse()?)
+}
+
+// 单个接口的流量统计
+#[derive(Debug, Clone, Default)]
+pub struct InterfaceTraffic {
+    // 网卡计数器，接口不存在时为 None
+    pub counters: Option<InterfaceCounters>,
+    // 接收和发送速率(bit/s)，需要两次采样才能计算
+    pub rx_rate: Option<f64>,
+    pub tx_rate: Option<f64>,
+    // nftables 中按标记统计的、经本机分流到该接口的流量
+    pub marked_packets: Option<u64>,
+    pub marked_bytes: Option<u64>,
+    pub marked_rate: Option<f64>,
+}
+
+impl InterfaceTraffic {
+    // 收发合计速率(bit/s)
+    pub fn throughput(&self) -> Option<f64> {
+        Some(self.rx_rate? + self.tx_rate?)
+    }
+}
+
+// 定期采样网卡计数器和 nftables 命名计数器，计算各接口的流量总量和速率
+#[derive(Default)]
+pub struct StatsCollector {
+    last_sample: Option<Instant>,
+    interfaces: HashMap<String, InterfaceTraffic>,
+    // 各策略分配出口的新连接数
+    policy_connections: HashMap<String, u64>,
+}
+
+impl StatsCollector {
+    pub async fn sample(&mut self, config: &Config, nftables: &NftablesManager) {
+        let now = Instant::now();
+        let elapsed = self.last_sample
+            .map(|then| now.duration_since(then).as_secs_f64())
+            .filter(|elapsed| *elapsed > 0.0);
+        
+        let counters = match nftables.read_counters().await {
+            Ok(counters) => counters,
+            Err(e) => {
+                tracing::debug!("读取 nftables 计数器失败: {}", e);
+                HashMap::new()
+            }
+        };
+        
+        let mut interfaces = HashMap::new();
+        for interface in &config.interfaces {
+            let previous = self.interfaces.get(&interface.name);
+            let mut traffic = InterfaceTraffic {
+                counters: read_sysfs_counters(&interface.interface_name).await.ok(),
+                ..Default::default()
+            };
+            if let Some(&(packets, bytes)) = counters.get(&NftablesManager::interface_counter(&interface.name)) {
+                traffic.marked_packets = Some(packets);
+                traffic.marked_bytes = Some(bytes);
+            }
+            
+            if let (Some(elapsed), Some(previous)) = (elapsed, previous) {
+                let before = previous.counters;
+                let after = traffic.counters;
+                traffic.rx_rate = rate(before.map(|c| c.rx_bytes), after.map(|c| c.rx_bytes), elapsed);
+                traffic.tx_rate = rate(before.map(|c| c.tx_bytes), after.map(|c| c.tx_bytes), elapsed);
+                traffic.marked_rate = rate(previous.marked_bytes, traffic.marked_bytes, elapsed);
+            }
+            interfaces.insert(interface.name.clone(), traffic);
+        }
+        
+        self.policy_connections = config.policies.iter()
+            .filter_map(|policy| {
+                let (packets, _) = counters.get(&NftablesManager::policy_counter(policy.name()))?;
+                Some((policy.name().to_string(), *packets))
+            })
+            .collect();
+        self.interfaces = interfaces;
+        self.last_sample = Some(now);
+    }
+    
+    pub fn interface(&self, name: &str) -> Option<&InterfaceTraffic> {
+        self.interfaces.get(name)
+    }
+    
+    // 各接口收发合计速率(bit/s)，尚无速率的接口不包含在内
+    pub fn throughput(&self) -> HashMap<String, f64> {
+        self.interfaces.iter()
+            .filter_map(|(name, traffic)| Some((name.clone(), traffic.throughput()?)))
+            .collect()
+    }
+    
+    pub fn policy_connections(&self, policy: &str) -> Option<u64> {
+        self.policy_connections.get(policy).copied()
+    }
+}
+
+// 计数器回绕或接口重建时读数变小，本次不计算速率
+fn rate(before: Option<u64>, after: Option<u64>, elapsed: f64) -> Option<f64> {
+    let delta = after?.checked_sub(before?)?;
+    Some(delta as f64 * 8.0 / elapsed)
+}
+
+// 以合适的单位显示字节数，按十进制单位换算，与配额配置保持一致
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+// 以合适的单位显示速率(bit/s)
+pub fn format_rate(bps: f64) -> String {
+    const UNITS: &[&str] = &["bps", "Kbps", "Mbps", "Gbps"];
+    let mut value = bps;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
\ No newline at end of file
@@ -0,0 +1,173 @@
+use std::path::Path;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+use crate::fsutil::write_atomic_async;
+use crate::overrides::Override;
+use crate::stats::{format_bytes, format_rate};
+
+// 守护进程定期写入状态目录的运行状态，供 status 子命令读取
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub updated: DateTime<Local>,
+    pub policy: String,
+    // 当前策略分配出口的新连接数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<u64>,
+    pub targets: Vec<TargetStatus>,
+    // 单独检测 IPv6 时的 IPv6 策略和出口
+    #[serde(rename = "policy-v6", default, skip_serializing_if = "Option::is_none")]
+    pub policy_v6: Option<String>,
+    #[serde(rename = "targets-v6", default, skip_serializing_if = "Vec::is_empty")]
+    pub targets_v6: Vec<TargetStatus>,
+    pub interfaces: Vec<InterfaceStatus>,
+    pub overrides: Vec<Override>,
+    // 当前生效的计划
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetStatus {
+    pub interface: String,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterfaceStatus {
+    pub name: String,
+    pub online: bool,
+    // 未启用 IPv6 的接口为 None
+    #[serde(rename = "online-v6", default, skip_serializing_if = "Option::is_none")]
+    pub online_v6: Option<bool>,
+    #[serde(rename = "latency-ms", default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    pub loss: f64,
+    #[serde(default)]
+    pub draining: bool,
+    // 排空中的接口剩余的连接数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flows: Option<usize>,
+    // 本计费周期已用流量和配额(字节)
+    #[serde(rename = "quota-used", default, skip_serializing_if = "Option::is_none")]
+    pub quota_used: Option<u64>,
+    #[serde(rename = "quota-limit", default, skip_serializing_if = "Option::is_none")]
+    pub quota_limit: Option<u64>,
+    // 网卡收发速率(bit/s)
+    #[serde(rename = "rx-rate", default, skip_serializing_if = "Option::is_none")]
+    pub rx_rate: Option<u64>,
+    #[serde(rename = "tx-rate", default, skip_serializing_if = "Option::is_none")]
+    pub tx_rate: Option<u64>,
+    // 经本机分流到该接口的流量总量(字节)和速率(bit/s)
+    #[serde(rename = "marked-bytes", default, skip_serializing_if = "Option::is_none")]
+    pub marked_bytes: Option<u64>,
+    #[serde(rename = "marked-rate", default, skip_serializing_if = "Option::is_none")]
+    pub marked_rate: Option<u64>,
+}
+
+impl StatusReport {
+    pub async fn save(&self, state_dir: &str) -> Result<()> {
+        let path = Path::new(state_dir).join("status.yaml");
+        write_atomic_async(path, serde_yaml::to_string(self)?).await
+    }
+    
+    pub async fn load(state_dir: &str) -> Result<Self> {
+        let path = Path::new(state_dir).join("status.yaml");
+        let content = tokio::fs::read_to_string(&path).await
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {} (is the daemon running?)", path.display(), e))?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+    
+    pub fn print(&self) {
+        println!("更新时间: {}", self.updated.format("%Y-%m-%d %H:%M:%S"));
+        match self.connections {
+            Some(connections) => println!("当前策略: {} (已分配 {} 个新连接)", self.policy, connections),
+            None => println!("当前策略: {}", self.policy),
+        }
+        
+        println!("出口接口: {}", format_targets(&self.targets));
+        if let Some(policy_v6) = &self.policy_v6 {
+            println!("IPv6 策略: {}", policy_v6);
+            println!("IPv6 出口: {}", format_targets(&self.targets_v6));
+        }
+        
+        println!("接口状态:");
+        for interface in &self.interfaces {
+            let latency = interface.latency_ms
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or_else(|| "-".to_string());
+            let ipv6 = match interface.online_v6 {
+                Some(true) => " IPv6 在线",
+                Some(false) => " IPv6 离线",
+                None => "",
+            };
+            let drain = match (interface.draining, interface.flows) {
+                (true, Some(flows)) => format!(" 排空中(剩余 {} 条连接)", flows),
+                (true, None) => " 排空中".to_string(),
+                _ => String::new(),
+            };
+            let rate = match (interface.rx_rate, interface.tx_rate) {
+                (Some(rx), Some(tx)) => format!(" 下行 {} 上行 {}", format_rate(rx as f64), format_rate(tx as f64)),
+                _ => String::new(),
+            };
+            let quota = match (interface.quota_used, interface.quota_limit) {
+                (Some(used), Some(limit)) => format!(" 流量 {}/{}", format_bytes(used), format_bytes(limit)),
+                _ => String::new(),
+            };
+            println!(
+                "  {:<12} {:<4} 延迟 {:<8} 丢包 {:.0}%{}{}{}{}",
+                interface.name,
+                if interface.online { "在线" } else { "离线" },
+                latency,
+                interface.loss * 100.0,
+                ipv6,
+                rate,
+                quota,
+                drain
+            );
+        }
+        
+        // 按标记计数器统计的分流比例
+        let marked: u64 = self.interfaces.iter().filter_map(|i| i.marked_bytes).sum();
+        if marked > 0 {
+            println!("流量分布:");
+            for interface in &self.interfaces {
+                let Some(bytes) = interface.marked_bytes else {
+                    continue;
+                };
+                let rate = interface.marked_rate
+                    .map(|r| format!(" {}", format_rate(r as f64)))
+                    .unwrap_or_default();
+                println!(
+                    "  {:<12} {:>5.1}% {}{}",
+                    interface.name,
+                    bytes as f64 * 100.0 / marked as f64,
+                    format_bytes(bytes),
+                    rate
+                );
+            }
+        }
+        
+        if !self.schedules.is_empty() {
+            println!("生效计划: {}", self.schedules.join(", "));
+        }
+        
+        if !self.overrides.is_empty() {
+            println!("覆盖规则:");
+            for entry in &self.overrides {
+                println!("  {}  {}", entry.id, entry.describe());
+            }
+        }
+    }
+}
+
+fn format_targets(targets: &[TargetStatus]) -> String {
+    if targets.is_empty() {
+        return "无".to_string();
+    }
+    targets.iter()
+        .map(|t| format!("{}({})", t.interface, t.weight))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
\ No newline at end of file
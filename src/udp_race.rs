@@ -9,12 +9,14 @@ use anyhow::Result;
 
 use crate::config::Config;
 
+#[allow(dead_code)]
 pub struct UdpRaceManager {
     config: Arc<RwLock<Config>>,
     active_races: Arc<RwLock<HashMap<u64, UdpRace>>>,
     race_counter: Arc<RwLock<u64>>,
 }
 
+#[allow(dead_code)]
 struct UdpRace {
     id: u64,
     sockets: Vec<UdpSocket>,
@@ -23,11 +25,12 @@ struct UdpRace {
     result_sender: mpsc::Sender<UdpRaceResult>,
 }
 
-struct UdpRaceResult {
-    race_id: u64,
-    interface: String,
-    response: Vec<u8>,
-    latency: Duration,
+#[allow(dead_code)]
+pub struct UdpRaceResult {
+    pub race_id: u64,
+    pub interface: String,
+    pub response: Vec<u8>,
+    pub latency: Duration,
 }
 
 impl UdpRaceManager {
@@ -53,6 +56,7 @@ impl UdpRaceManager {
         }
     }
     
+    #[allow(dead_code)]
     pub async fn start_race(&self, target: SocketAddr, data: Vec<u8>) -> Result<u64> {
         let config = self.config.read().await;
         
@@ -65,7 +69,7 @@ impl UdpRaceManager {
         let race_id = *counter;
         drop(counter);
         
-        let (result_sender, mut result_receiver) = mpsc::channel(32);
+        let (result_sender, _result_receiver) = mpsc::channel(32);
         
         // 为每个接口创建 UDP socket 占位
         let sockets = self.create_race_sockets(&config.interfaces).await?;
@@ -87,6 +91,7 @@ impl UdpRaceManager {
         Ok(race_id)
     }
     
+    #[allow(dead_code)]
     async fn create_race_sockets(&self, interfaces: &[crate::config::Interface]) -> Result<Vec<UdpSocket>> {
         let mut sockets = Vec::new();
         
@@ -101,6 +106,7 @@ impl UdpRaceManager {
         Ok(sockets)
     }
     
+    #[allow(dead_code)]
     async fn execute_race(&self, race: &UdpRace) -> Result<()> {
         // 执行并发 UDP 发送占位
         for i in 0..race.sockets.len() {
@@ -113,7 +119,7 @@ impl UdpRaceManager {
             let socket = UdpSocket::bind("0.0.0.0:0").await?;
             
             tokio::spawn(async move {
-                if let Ok(_) = socket.send_to(&data, target).await {
+                if socket.send_to(&data, target).await.is_ok() {
                     // 等待响应占位
                     let mut buf = vec![0u8; 1024];
                     if let Ok((len, _)) = socket.recv_from(&mut buf).await {
@@ -150,7 +156,8 @@ impl UdpRaceManager {
         Ok(())
     }
     
-    pub async fn get_race_result(&self, race_id: u64, timeout_duration: Duration) -> Result<Option<UdpRaceResult>> {
+    #[allow(dead_code)]
+    pub async fn get_race_result(&self, _race_id: u64, timeout_duration: Duration) -> Result<Option<UdpRaceResult>> {
         // 获取 race 结果占位
         timeout(timeout_duration, async {
             // 等待结果占位